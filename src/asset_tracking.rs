@@ -1,6 +1,6 @@
 //! A high-level way to load collections of asset handles as resources.
 
-use std::collections::VecDeque;
+use std::{collections::VecDeque, marker::PhantomData};
 
 use bevy::prelude::*;
 
@@ -14,6 +14,21 @@ pub trait LoadResource {
     /// have been loaded, it will be inserted as a resource. This ensures that the resource only
     /// exists when the assets are ready.
     fn load_resource<T: Resource + Asset + Clone + FromWorld>(&mut self) -> &mut Self;
+
+    /// Like [`load_resource`](Self::load_resource), but reads the [`Asset`] from `path` with its
+    /// registered [`AssetLoader`](bevy::asset::AssetLoader) instead of constructing it in code.
+    fn load_resource_from_path<T: Resource + Asset + Clone>(
+        &mut self,
+        path: &'static str,
+    ) -> &mut Self;
+
+    /// Like [`load_resource_from_path`](Self::load_resource_from_path), but keeps the [`Resource`]
+    /// in sync with the [`Asset`], so edits to the file show up while the game runs (with the
+    /// `file_watcher` feature). Every reload writes a [`LiveResourceReloaded`] message.
+    fn load_live_resource_from_path<T: Resource + Asset + Clone>(
+        &mut self,
+        path: &'static str,
+    ) -> &mut Self;
 }
 
 impl LoadResource for App {
@@ -26,14 +41,76 @@ impl LoadResource for App {
         let mut handles = world.resource_mut::<ResourceHandles>();
         handles
             .waiting
-            .push_back((handle.untyped(), |world, handle| {
-                let assets = world.resource::<Assets<T>>();
-                if let Some(value) = assets.get(handle.id().typed::<T>()) {
-                    world.insert_resource(value.clone());
-                }
-            }));
+            .push_back((handle.untyped(), insert_loaded_resource::<T>));
+        self
+    }
+
+    fn load_resource_from_path<T: Resource + Asset + Clone>(
+        &mut self,
+        path: &'static str,
+    ) -> &mut Self {
+        self.init_asset::<T>();
+        let world = self.world_mut();
+        let handle: Handle<T> = world.resource::<AssetServer>().load(path);
+        let mut handles = world.resource_mut::<ResourceHandles>();
+        handles
+            .waiting
+            .push_back((handle.untyped(), insert_loaded_resource::<T>));
         self
     }
+
+    fn load_live_resource_from_path<T: Resource + Asset + Clone>(
+        &mut self,
+        path: &'static str,
+    ) -> &mut Self {
+        self.load_resource_from_path::<T>(path);
+        let handle: Handle<T> = self.world().resource::<AssetServer>().load(path);
+        self.insert_resource(LiveResource(handle));
+        self.add_message::<LiveResourceReloaded<T>>();
+        self.add_systems(
+            PreUpdate,
+            sync_live_resource::<T>.after(load_resource_assets),
+        );
+        self
+    }
+}
+
+/// Handle of a resource loaded with
+/// [`load_live_resource_from_path`](LoadResource::load_live_resource_from_path)
+#[derive(Resource)]
+struct LiveResource<T: Asset>(Handle<T>);
+
+/// Written when a resource loaded with
+/// [`load_live_resource_from_path`](LoadResource::load_live_resource_from_path) is replaced by
+/// its reloaded asset, but not when it is first inserted
+#[derive(Message)]
+pub struct LiveResourceReloaded<T: Resource>(PhantomData<T>);
+
+/// Re-inserts a live resource whenever its asset is reloaded
+fn sync_live_resource<T: Resource + Asset + Clone>(
+    mut commands: Commands,
+    mut events: MessageReader<AssetEvent<T>>,
+    mut reloaded: MessageWriter<LiveResourceReloaded<T>>,
+    live: Res<LiveResource<T>>,
+    assets: Res<Assets<T>>,
+) {
+    for event in events.read() {
+        if !event.is_modified(&live.0) {
+            continue;
+        }
+        if let Some(value) = assets.get(&live.0) {
+            info!("Reloaded {}", std::any::type_name::<T>());
+            commands.insert_resource(value.clone());
+            reloaded.write(LiveResourceReloaded(PhantomData));
+        }
+    }
+}
+
+fn insert_loaded_resource<T: Resource + Asset + Clone>(world: &mut World, handle: &UntypedHandle) {
+    let assets = world.resource::<Assets<T>>();
+    if let Some(value) = assets.get(handle.id().typed::<T>()) {
+        world.insert_resource(value.clone());
+    }
 }
 
 /// A function that inserts a loaded resource.
@@ -1,10 +1,24 @@
 //! Development tools for the game. This plugin is only enabled in dev builds.
 
+use std::{collections::HashMap, fmt::Write};
+
 use bevy::{
     dev_tools::states::log_transitions, input::common_conditions::input_just_pressed, prelude::*,
 };
+use bevy_tnua::prelude::*;
 
-use crate::screens::Screen;
+use crate::{
+    game::{
+        animations::{
+            animation_controller::AnimationNodes,
+            models::{AnimationState, CharacterAnimationController},
+            transitions::StateTransitions,
+        },
+        configs::{AnimationBlendingConfig, AnimationStateMachine},
+        player::{Dodge, Parkour, Player},
+    },
+    screens::Screen,
+};
 
 pub(super) fn plugin(app: &mut App) {
     // Log `Screen` state transitions.
@@ -16,10 +30,161 @@ pub(super) fn plugin(app: &mut App) {
         Update,
         toggle_debug_ui.run_if(input_just_pressed(TOGGLE_KEY)),
     );
+
+    // Toggle the overlay showing what the player's animation is doing.
+    app.add_systems(
+        Update,
+        (
+            toggle_animation_overlay
+                .run_if(in_state(Screen::Gameplay).and(input_just_pressed(ANIMATION_OVERLAY_KEY))),
+            update_animation_overlay.run_if(any_with_component::<AnimationOverlay>),
+        )
+            .chain(),
+    );
 }
 
 const TOGGLE_KEY: KeyCode = KeyCode::Backquote;
 
+const ANIMATION_OVERLAY_KEY: KeyCode = KeyCode::F3;
+
 fn toggle_debug_ui(mut options: ResMut<UiDebugOptions>) {
     options.toggle();
 }
+
+/// Text overlay listing the player's animation state, graph nodes and Tnua state
+#[derive(Component)]
+struct AnimationOverlay;
+
+fn toggle_animation_overlay(
+    mut commands: Commands,
+    overlay: Query<Entity, With<AnimationOverlay>>,
+) {
+    if let Ok(overlay) = overlay.single() {
+        commands.entity(overlay).despawn();
+        return;
+    }
+    commands.spawn((
+        Name::new("Animation Overlay"),
+        AnimationOverlay,
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(10),
+            left: px(10),
+            padding: UiRect::all(px(8)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        GlobalZIndex(2),
+        Pickable::IGNORE,
+        Text::default(),
+        TextFont::from_font_size(14.0),
+        DespawnOnExit(Screen::Gameplay),
+    ));
+}
+
+fn update_animation_overlay(
+    mut overlay: Single<&mut Text, With<AnimationOverlay>>,
+    characters: Query<
+        (
+            &AnimationState,
+            &AnimationNodes,
+            &CharacterAnimationController,
+            &TnuaController,
+            Option<&Dodge>,
+            Option<&Parkour>,
+        ),
+        With<Player>,
+    >,
+    animation_players: Query<(&AnimationPlayer, &StateTransitions)>,
+    state_machine: Option<Res<AnimationStateMachine>>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
+) {
+    let mut text = String::new();
+    let Ok((state, nodes, controller, tnua, dodge, parkour)) = characters.single() else {
+        overlay.0 = "No animated player".to_string();
+        return;
+    };
+
+    // Graph nodes are added in the order of their state's clips
+    let mut labels = HashMap::new();
+    if let (Some(state_machine), Some(blend_config)) = (&state_machine, &blend_config) {
+        for (name, state_nodes) in &nodes.states {
+            let Some(definition) = state_machine.states.get(name) else {
+                continue;
+            };
+            for (node, clip) in state_nodes
+                .iter()
+                .zip(definition.motion.clips(blend_config))
+            {
+                labels.insert(*node, format!("{name}/{}", clip.name));
+            }
+        }
+    }
+    for (name, node) in &nodes.layers {
+        labels.insert(*node, format!("layer {name}"));
+    }
+
+    let _ = writeln!(text, "State: {}", state.current);
+    let _ = writeln!(
+        text,
+        "Tnua: basis {}, action {} ({:?})",
+        tnua.basis_name().unwrap_or("none"),
+        tnua.action_name().unwrap_or("none"),
+        tnua.action_flow_status()
+    );
+    if let Some(dodge) = dodge {
+        let _ = writeln!(
+            text,
+            "Dodge: {}{}",
+            if dodge.is_ready() {
+                "ready"
+            } else {
+                "cooling down"
+            },
+            if dodge.is_invulnerable() {
+                ", invulnerable"
+            } else {
+                ""
+            }
+        );
+    }
+    if let Some(obstacle) = parkour.and_then(|parkour| parkour.ahead) {
+        let _ = writeln!(
+            text,
+            "Ahead: {:?}, {:.2}m high, {:.2}m long, {:.2}m away, edge at {:.2}",
+            obstacle.kind, obstacle.height, obstacle.length, obstacle.distance, obstacle.edge
+        );
+    }
+
+    if let Ok((animation_player, transitions)) = animation_players.get(controller.animation_player)
+    {
+        let _ = writeln!(text, "Transition: {:.0}%", transitions.progress() * 100.0);
+        for (name, weight) in transitions.weights() {
+            let _ = writeln!(text, "  {name}: {weight:.2}");
+        }
+
+        let _ = writeln!(text, "Nodes:");
+        let mut playing: Vec<_> = animation_player.playing_animations().collect();
+        playing.sort_by(|(_, a), (_, b)| b.weight().total_cmp(&a.weight()));
+        for (node, animation) in playing {
+            let label = labels
+                .get(node)
+                .cloned()
+                .unwrap_or_else(|| format!("node {}", node.index()));
+            let _ = writeln!(
+                text,
+                "  {label}: weight {:.2}, time {:.2}s, speed {:.2}{}",
+                animation.weight(),
+                animation.seek_time(),
+                animation.speed(),
+                if animation.is_paused() {
+                    ", paused"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    overlay.0 = text;
+}
@@ -1,261 +1,646 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
-use bevy::{animation, prelude::*};
-use bevy_tnua::{TnuaAnimatingState, TnuaAnimatingStateDirective, builtins::TnuaBuiltinJumpState, prelude::*};
+use avian3d::prelude::*;
+use bevy::{
+    animation::{ActiveAnimation, AnimationTarget},
+    prelude::*,
+};
+use bevy_tnua::{
+    builtins::{TnuaBuiltinCrouch, TnuaBuiltinCrouchState, TnuaBuiltinJumpState},
+    prelude::*,
+};
 
-use crate::game::player::{self, MovementController, Player, PlayerAssets};
+use crate::{
+    asset_tracking::LiveResourceReloaded,
+    game::{
+        configs::{
+            AnimationBlendingConfig, AnimationStateMachine, JumpPhase, MotionClip, StateMotion,
+            TransitionCondition, TransitionMode,
+        },
+        player::{MovementController, PlayerAssets},
+    },
+};
 
-use super::models::{AnimationState, CharacterAnimationController};
+use super::{
+    blend_space::{directional_weights, locomotion_weights},
+    events::clip_with_markers,
+    inertialization::Inertialization,
+    layers::{additive_clip, build_mask_groups, layer_mask},
+    models::{AnimationParameters, AnimationState, CharacterAnimationController, StateTurn},
+    retargeting::{RetargetRig, Retargeting},
+    root_motion::RootMotion,
+    sync_groups::sync_clip_phases,
+    transitions::StateTransitions,
+};
 
-/// Stores the indices of animation nodes in the animation graph
-#[derive(Resource)]
+/// Stores the indices of animation nodes in a character's animation graph, keyed by state name
+///
+/// Every state owns one node per clip of its motion, in the order returned by
+/// [`StateMotion::clips`]. Layers own a single node, keyed by layer name.
+#[derive(Component)]
 pub struct AnimationNodes {
-    pub idle: AnimationNodeIndex,
-    pub walk: AnimationNodeIndex,
-    pub run: AnimationNodeIndex,
-    pub jump: AnimationNodeIndex,
-    pub running_jump: AnimationNodeIndex,
-    pub fall: AnimationNodeIndex,
+    pub states: HashMap<String, Vec<AnimationNodeIndex>>,
+    pub layers: HashMap<String, AnimationNodeIndex>,
 }
 
-/// Creates the animation graph with all clips and transitions
+/// Creates an animation graph with clip nodes for every state of the state machine, for each
+/// character whose [`AnimationPlayer`] has spawned
+///
+/// The graph's node indices go on the character next to its [`AnimationState`], the graph itself
+/// on the [`AnimationPlayer`] somewhere in the character's scene.
 pub fn setup_animation_graph(
     mut commands: Commands,
     player_assets: Option<Res<PlayerAssets>>,
+    state_machine: Option<Res<AnimationStateMachine>>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
     mut graphs: ResMut<Assets<AnimationGraph>>,
-    animation_nodes: Option<Res<AnimationNodes>>,
-    mut animation_player_query: Query<(Entity, &mut AnimationPlayer), Added<AnimationPlayer>>,
+    mut clips: ResMut<Assets<AnimationClip>>,
+    mut marked_clips: Local<HashMap<String, Handle<AnimationClip>>>,
+    mut additive_clips: Local<HashMap<String, Handle<AnimationClip>>>,
+    mut missing_clips: Local<HashSet<String>>,
+    animation_player_query: Query<Entity, (With<AnimationPlayer>, Without<StateTransitions>)>,
+    // Retargeted characters wait for their bones to be mapped to the clip rig
+    characters: Query<
+        Option<&RetargetRig>,
+        (
+            With<AnimationState>,
+            Without<AnimationNodes>,
+            Or<(Without<Retargeting>, With<RetargetRig>)>,
+        ),
+    >,
+    targets: Query<(Entity, &AnimationTarget)>,
+    names: Query<&Name>,
+    parents: Query<&ChildOf>,
 ) {
-    // If animation nodes exist, no need to process this anymore
-    if let Some(_) = animation_nodes {
+    let (Some(player_assets), Some(state_machine), Some(blend_config)) =
+        (player_assets, state_machine, blend_config)
+    else {
         return;
+    };
+    // Reloaded markers need fresh clip copies
+    if blend_config.is_changed() {
+        marked_clips.clear();
+    }
+    // Reloaded layers or clips need fresh offsets
+    if state_machine.is_changed() || player_assets.is_changed() {
+        additive_clips.clear();
     }
 
-    let Some(player_assets) = player_assets else {
-        return;
-    };
-    // This needs to be all players
-    let Ok((animation_player_entity, mut animation_player)) = animation_player_query.single_mut() else {
-        return;
-    };
+    for animation_player_entity in &animation_player_query {
+        let Some((character, retarget_rig)) = parents
+            .iter_ancestors(animation_player_entity)
+            .find_map(|ancestor| Some((ancestor, characters.get(ancestor).ok()?)))
+        else {
+            continue;
+        };
+        let (graph, animation_nodes) = build_animation_graph(
+            &player_assets,
+            &state_machine,
+            &blend_config,
+            &mut clips,
+            &mut marked_clips,
+            &mut additive_clips,
+            &mut missing_clips,
+            retarget_rig,
+            animation_player_entity,
+            &targets,
+            &names,
+            &parents,
+        );
 
-    let mut graph = AnimationGraph::new();
-    let root_node = graph.root;
-
-    let animations = &player_assets.animations;
-
-    // Add all animation clips as nodes
-    let idle_node = graph.add_clip(animations.idle.clone(), 1.0, root_node);
-    let walk_node = graph.add_clip(animations.walking.clone(), 1.0, root_node);
-    let run_node = graph.add_clip(animations.running.clone(), 1.0, root_node);
-    let jump_node = graph.add_clip(animations.standing_jump.clone(), 1.0, root_node);
-    let running_jump_node = graph.add_clip(animations.running_jump.clone(), 1.0, root_node);
-    // Note: Reusing standing_jump for falling since we don't have a dedicated falling animation yet
-    let fall_node = graph.add_clip(animations.standing_jump.clone(), 1.0, root_node);
-
-    // Store the graph and node indices
-    let graph_handle = graphs.add(graph);
-
-    commands.insert_resource(AnimationNodes {
-        idle: idle_node,
-        walk: walk_node,
-        run: run_node,
-        jump: jump_node,
-        fall: fall_node,
-        running_jump: running_jump_node,
-    });
-    let mut transitions = AnimationTransitions::new();
-    transitions
-        .play(
-            &mut animation_player,
-            idle_node,
-            Duration::ZERO)
-        .repeat();
-
-    // Store the graph handle as a resource for easy access
-    commands
-    .entity(animation_player_entity)
-    .insert(AnimationGraphHandle(graph_handle))
-    .insert(transitions)
-    ;
-
-    info!("Animation graph successfully created with unified GLTF animations!");
-}
+        // Store the graph and node indices
+        let graph_handle = graphs.add(graph);
+        commands.entity(character).insert((
+            animation_nodes,
+            CharacterAnimationController {
+                graph: graph_handle.clone(),
+                animation_player: animation_player_entity,
+            },
+        ));
+        commands.entity(animation_player_entity).insert((
+            AnimationGraphHandle(graph_handle),
+            StateTransitions::default(),
+            Inertialization::default(),
+        ));
 
+        info!(
+            "Animation graph created with {} states for {character}",
+            state_machine.states.len()
+        );
+    }
+}
 
-/// Updates animation state based on Tnua controller state
-pub fn update_animation_state(
-    mut player_query: Query<
-        (&TnuaController, &mut TnuaAnimatingState<AnimationState>),
-        With<Player>,
-    >,
-    mut animation_player_query: Query<(&mut AnimationPlayer, &mut AnimationTransitions)>,
-    animation_nodes: Option<Res<AnimationNodes>>,
+/// Throws away the animation graphs when the state machine or blending config is reloaded, so
+/// [`setup_animation_graph`] rebuilds them from the new configs
+///
+/// Characters restart in the initial state. Blend weights are read from the config every frame
+/// and need no rebuild, but states, clips and markers are baked into the graph. A new blending
+/// config can also assign other clips to the slots, so [`PlayerAssets`] is extracted again.
+pub fn rebuild_animation_graphs(
+    mut commands: Commands,
+    mut character_query: Query<(Entity, &CharacterAnimationController, &mut AnimationState)>,
+    mut animation_player_query: Query<&mut AnimationPlayer>,
+    mut blend_config_reloads: MessageReader<LiveResourceReloaded<AnimationBlendingConfig>>,
+    mut state_machine_reloads: MessageReader<LiveResourceReloaded<AnimationStateMachine>>,
 ) {
-    let Ok((mut animation_player, mut transitions)) = animation_player_query.single_mut() else {
-        return;
-    };
-    let Some(animation_nodes) = animation_nodes else {
+    let blend_config_reloaded = !blend_config_reloads.is_empty();
+    if !blend_config_reloaded && state_machine_reloads.is_empty() {
         return;
+    }
+    blend_config_reloads.clear();
+    state_machine_reloads.clear();
+
+    if blend_config_reloaded {
+        commands.remove_resource::<PlayerAssets>();
+    }
+    for (character, animation_controller, mut animation_state) in &mut character_query {
+        animation_state.current.clear();
+        animation_state.turn = None;
+        if let Ok(mut animation_player) =
+            animation_player_query.get_mut(animation_controller.animation_player)
+        {
+            animation_player.stop_all();
+        }
+        commands
+            .entity(character)
+            .remove::<(AnimationNodes, CharacterAnimationController)>();
+        commands
+            .entity(animation_controller.animation_player)
+            .remove::<(StateTransitions, Inertialization)>();
+    }
+}
+
+/// Builds the animation graph of a single [`AnimationPlayer`]
+///
+/// Clips with event markers are copied once into `marked_clips`, and the offsets played by
+/// additive layers once into `additive_clips`, keyed by layer. Both are shared by every character.
+/// States and layers whose clip is missing are left out, which is reported once per clip in
+/// `missing_clips`. Characters with a [`RetargetRig`] get their own copies of the clips, keyed to their bones.
+fn build_animation_graph(
+    player_assets: &PlayerAssets,
+    state_machine: &AnimationStateMachine,
+    blend_config: &AnimationBlendingConfig,
+    clips: &mut Assets<AnimationClip>,
+    marked_clips: &mut HashMap<String, Handle<AnimationClip>>,
+    additive_clips: &mut HashMap<String, Handle<AnimationClip>>,
+    missing_clips: &mut HashSet<String>,
+    retarget_rig: Option<&RetargetRig>,
+    animation_player_entity: Entity,
+    targets: &Query<(Entity, &AnimationTarget)>,
+    names: &Query<&Name>,
+    parents: &Query<&ChildOf>,
+) -> (AnimationGraph, AnimationNodes) {
+    let mut graph = AnimationGraph::new();
+    // Everything but the additive layers blends below `root_node`, the additive layers are
+    // added on top of that blend
+    let additive_node = graph.add_additive_blend(1.0, graph.root);
+    let root_node = graph.add_blend(1.0, additive_node);
+    let mut retargeted_clips = HashMap::new();
+    let mut retarget = |clips: &mut Assets<AnimationClip>, clip: Handle<AnimationClip>| {
+        let Some(rig) = retarget_rig else {
+            return clip;
+        };
+        retargeted_clips
+            .entry(clip.id())
+            .or_insert_with(|| match clips.get(&clip) {
+                Some(source) => {
+                    let retargeted = rig.retarget_clip(source);
+                    clips.add(retargeted)
+                }
+                None => clip.clone(),
+            })
+            .clone()
     };
 
-    for (controller, mut animating_state) in player_query.iter_mut() {
-        let new_state = determine_animation_state(controller);
-        apply_animation_state(&mut animating_state, new_state, &mut animation_player, &mut transitions, &animation_nodes);
+    // Add separate nodes per state, so states sharing a clip can still play it independently
+    let mut states = HashMap::new();
+    'states: for (name, state) in &state_machine.states {
+        let mut nodes = Vec::new();
+        for MotionClip {
+            name: clip_name, ..
+        } in state.motion.clips(blend_config)
+        {
+            let Some(clip) = player_assets.clips.get(&clip_name) else {
+                if missing_clips.insert(clip_name.clone()) {
+                    warn!(
+                        "Animation state `{name}` is left out, its clip `{clip_name}` is missing"
+                    );
+                }
+                continue 'states;
+            };
+            // Copy each clip with event markers once, no matter how many states play it
+            let clip = marked_clips
+                .entry(clip_name.clone())
+                .or_insert_with(|| {
+                    let markers = blend_config.markers.get(&clip_name);
+                    clip_with_markers(clips, clip, markers.map_or(&[], Vec::as_slice))
+                })
+                .clone();
+            let clip = retarget(clips, clip);
+            nodes.push(graph.add_clip(clip, 1.0, root_node));
+        }
+        states.insert(name.clone(), nodes);
+    }
 
+    // Layers only animate the bones of their mask group
+    let groups = build_mask_groups(
+        &mut graph,
+        state_machine,
+        animation_player_entity,
+        targets,
+        names,
+        parents,
+    );
+    let mut layers = HashMap::new();
+    for (name, layer) in &state_machine.layers {
+        let Some(clip) = player_assets.clips.get(&layer.clip) else {
+            if missing_clips.insert(layer.clip.clone()) {
+                warn!(
+                    "Animation layer `{name}` is left out, its clip `{}` is missing",
+                    layer.clip
+                );
+            }
+            continue;
+        };
+        let (clip, parent) = if layer.additive {
+            let reference = layer
+                .reference
+                .as_ref()
+                .and_then(|reference| player_assets.clips.get(reference));
+            let clip = additive_clips
+                .entry(name.clone())
+                .or_insert_with(|| additive_clip(clips, clip, reference))
+                .clone();
+            (clip, additive_node)
+        } else {
+            (clip.clone(), root_node)
+        };
+        let clip = retarget(clips, clip);
+        let mask = layer_mask(groups[&layer.mask]);
+        layers.insert(
+            name.clone(),
+            graph.add_clip_with_mask(clip, mask, 1.0, parent),
+        );
     }
+
+    (graph, AnimationNodes { states, layers })
 }
 
-/// Determines which animation state to use based on Tnua controller
-pub fn determine_animation_state(controller: &TnuaController) -> AnimationState {
-    let current_status_for_animating = match controller.action_name() {
-        Some(TnuaBuiltinJump::NAME) => {
-            // Jump action is active - play the full jump animation sequence
-            // The standing_jump animation includes the full jump and landing, so we
-            // don't need to check the jump state or handle falling separately
-            AnimationState::Jumping
-        }
-        Some("jump") => {
-            AnimationState::Jumping
-        }
-        // Tnua should only have the `action_name` of the actions you feed to it. If it has
-        // anything else - consider it a bug.
-        Some(other) => {
-            warn!("Unknown action {other}");
-            AnimationState::Idle
+/// Evaluates the animation state machine of every character against its Tnua controller state
+///
+/// Characters without a Tnua controller or player controls, like NPCs and previews, animate from
+/// their velocity alone.
+pub fn update_animation_state(
+    mut character_query: Query<(
+        Option<&TnuaController>,
+        Option<&mut MovementController>,
+        &Transform,
+        Option<&LinearVelocity>,
+        &mut AnimationState,
+        &AnimationNodes,
+        &CharacterAnimationController,
+        Option<&mut RootMotion>,
+    )>,
+    mut animation_player_query: Query<(
+        &mut AnimationPlayer,
+        &mut StateTransitions,
+        &mut Inertialization,
+    )>,
+    state_machine: Option<Res<AnimationStateMachine>>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
+    graphs: Res<Assets<AnimationGraph>>,
+    clips: Res<Assets<AnimationClip>>,
+    time: Res<Time>,
+) {
+    let (Some(state_machine), Some(blend_config)) = (state_machine, blend_config) else {
+        return;
+    };
+
+    for (
+        controller,
+        movement,
+        transform,
+        velocity,
+        mut animation_state,
+        animation_nodes,
+        animation_controller,
+        root_motion,
+    ) in &mut character_query
+    {
+        let Ok((mut animation_player, mut transitions, mut inertialization)) =
+            animation_player_query.get_mut(animation_controller.animation_player)
+        else {
+            continue;
+        };
+        let Some(graph) = graphs.get(&animation_controller.graph) else {
+            continue;
+        };
+
+        // The velocity of the last airborne frame is the speed the character lands with
+        let airborne =
+            controller.is_some_and(|controller| controller.is_airborne().unwrap_or(false));
+        if let Some(velocity) = velocity.filter(|_| airborne) {
+            animation_state.impact_speed = (-velocity.y).max(0.0);
         }
-        // No action name means that no action is currently being performed - which means the
-        // animation should be decided by the basis.
-        None => {
-            // If there is no action going on, we'll base the animation on the state of the
-            // basis.
-            let Some((_, basis_state)) = controller.concrete_basis::<TnuaBuiltinWalk>() else {
-                // Since we only use the walk basis in this example, if we can't get get this
-                // basis' state it probably means the system ran before any basis was set, so we
-                // just skip this frame.
-                return AnimationState::Idle;
-            };
 
-            // Speed threshold for idle
-            const IDLE_THRESHOLD: f32 = 0.1;  // Below this = idle
+        let finished = animation_nodes
+            .states
+            .get(&animation_state.current)
+            .is_none_or(|nodes| {
+                nodes.iter().all(|node| {
+                    animation_player
+                        .animation(*node)
+                        .is_none_or(ActiveAnimation::is_finished)
+                })
+            });
+        let params = animation_parameters(
+            controller,
+            movement.as_deref(),
+            transform,
+            velocity,
+            &animation_state,
+            finished,
+        );
 
-            const WALK_THRESHOLD: f32 = 2.0;  // Below this = idle
+        // Freshly spawned characters start in the initial state without blending
+        let next = if animation_state.current.is_empty() {
+            Some((
+                state_machine.initial_state.clone(),
+                Duration::ZERO,
+                TransitionMode::CrossFade,
+            ))
+        } else {
+            // States whose clips are missing from the GLTF have no nodes and can't be entered
+            state_machine
+                .transitions_from(&animation_state.current)
+                .find(|transition| {
+                    animation_nodes.states.contains_key(&transition.to)
+                        && transition
+                            .conditions
+                            .iter()
+                            .all(|condition| condition_met(condition, &params))
+                })
+                .map(|transition| {
+                    (
+                        transition.to.clone(),
+                        Duration::from_secs_f32(transition.blend_duration),
+                        transition.mode,
+                    )
+                })
+        };
 
-            let speed = basis_state.running_velocity.length();
-            if speed < IDLE_THRESHOLD {
-                AnimationState::Idle
-            } else if speed <= WALK_THRESHOLD {
-                AnimationState::Walking
+        if let Some((next_state, blend_duration, mode)) = next {
+            debug!(
+                "Animation state: {} -> {}",
+                animation_state.current, next_state
+            );
+            play_state(
+                &state_machine,
+                animation_nodes,
+                &blend_config,
+                &next_state,
+                &mut animation_player,
+            );
+            match mode {
+                TransitionMode::CrossFade => transitions.play(&next_state, blend_duration),
+                // The old states stop right away, their pose lives on as a decaying offset
+                TransitionMode::Inertialize => {
+                    transitions.play(&next_state, Duration::ZERO);
+                    inertialization.start(blend_duration.as_secs_f32());
+                }
             }
-            else {
-                // Any movement uses the Moving state with the actual speed
-                // The blend between walk and run animations will be handled automatically
-                AnimationState::Running(speed)
+
+            // Turning states rotate from the current facing to the input direction
+            animation_state.turn = state_machine
+                .states
+                .get(&next_state)
+                .filter(|state| state.sync_turn)
+                .map(|_| StateTurn {
+                    from: transform.forward(),
+                    angle: params.turn_angle.to_radians(),
+                });
+            animation_state.current = next_state;
+        }
+
+        let current_state = state_machine.states.get(&animation_state.current);
+        if let Some(mut root_motion) = root_motion {
+            root_motion.active = current_state.is_some_and(|state| state.root_motion);
+        }
+        if let Some(mut movement) = movement {
+            movement.animated_forward = animation_state.turn.and_then(|turn| {
+                let node = *animation_nodes
+                    .states
+                    .get(&animation_state.current)?
+                    .first()?;
+                let progress = clip_progress(node, &animation_player, graph, &clips)?;
+                Dir3::new(Quat::from_rotation_y(turn.angle * progress) * *turn.from).ok()
+            });
+            movement.movement_locked = current_state.is_some_and(|state| state.lock_movement);
+        }
+
+        for state in transitions.advance(time.delta_secs()) {
+            for &node in animation_nodes.states.get(&state).into_iter().flatten() {
+                animation_player.stop(node);
             }
         }
+        apply_state_weights(
+            &state_machine,
+            animation_nodes,
+            &blend_config,
+            &transitions,
+            &params,
+            &mut animation_player,
+        );
+        sync_clip_phases(
+            &state_machine,
+            animation_nodes,
+            &blend_config,
+            &transitions,
+            graph,
+            &clips,
+            &mut animation_player,
+        );
+    }
+}
+
+/// Collects the movement parameters that state machine transitions are checked against
+///
+/// Without a Tnua controller the character is grounded, without an action, and moves at the
+/// horizontal part of its velocity.
+pub fn animation_parameters(
+    controller: Option<&TnuaController>,
+    movement: Option<&MovementController>,
+    transform: &Transform,
+    linear_velocity: Option<&LinearVelocity>,
+    animation_state: &AnimationState,
+    finished: bool,
+) -> AnimationParameters {
+    let linear_velocity = linear_velocity.map_or(Vec3::ZERO, |velocity| velocity.0);
+    // Since we only use the walk basis, a missing basis means the system ran before any basis
+    // was set, so the character is treated as standing still.
+    let velocity = match controller {
+        Some(controller) => controller
+            .concrete_basis::<TnuaBuiltinWalk>()
+            .map_or(Vec3::ZERO, |(_, basis_state)| basis_state.running_velocity),
+        None => linear_velocity.reject_from(Vec3::Y),
+    };
+
+    let forward = transform.forward();
+    let input = movement.map_or(Vec3::ZERO, |movement| movement.input_direction);
+    let turn_angle = if input == Vec3::ZERO {
+        0.0
+    } else {
+        forward
+            .cross(input)
+            .y
+            .atan2(forward.dot(input))
+            .to_degrees()
     };
-    current_status_for_animating
 
+    AnimationParameters {
+        speed: velocity.length(),
+        local_velocity: Vec2::new(velocity.dot(*transform.right()), velocity.dot(*forward)),
+        turn_angle,
+        vertical_speed: linear_velocity.y,
+        grounded: controller.is_none_or(|controller| !controller.is_airborne().unwrap_or(false)),
+        jump_phase: controller
+            .and_then(|controller| controller.concrete_action::<TnuaBuiltinJump>())
+            .and_then(|(_, jump_state)| jump_phase(jump_state)),
+        impact_speed: animation_state.impact_speed,
+        action: controller.and_then(TnuaController::action_name),
+        aiming: movement.is_some_and(|movement| movement.is_aiming),
+        crouching: controller
+            .and_then(|controller| controller.concrete_action::<TnuaBuiltinCrouch>())
+            .is_some_and(|(_, crouch_state)| {
+                !matches!(crouch_state, TnuaBuiltinCrouchState::Rising)
+            }),
+        finished,
+    }
 }
 
-/// Applies the current animation state to the animation player with blending
-fn apply_animation_state(
-    animating_state: &mut TnuaAnimatingState<AnimationState>,
-    new_state: AnimationState,
-    animation_player: &mut AnimationPlayer,
-    transitions: &mut AnimationTransitions,
+/// Maps the state of Tnua's jump action to the phase the animation cares about
+fn jump_phase(jump_state: &TnuaBuiltinJumpState) -> Option<JumpPhase> {
+    match jump_state {
+        TnuaBuiltinJumpState::NoJump => None,
+        TnuaBuiltinJumpState::StartingJump { .. }
+        | TnuaBuiltinJumpState::SlowDownTooFastSlopeJump { .. }
+        | TnuaBuiltinJumpState::MaintainingJump { .. } => Some(JumpPhase::Rise),
+        TnuaBuiltinJumpState::StoppedMaintainingJump => Some(JumpPhase::Apex),
+        TnuaBuiltinJumpState::FallSection => Some(JumpPhase::Fall),
+    }
+}
+
+/// Checks a single transition condition against the current movement parameters
+fn condition_met(condition: &TransitionCondition, params: &AnimationParameters) -> bool {
+    match condition {
+        TransitionCondition::SpeedAbove(speed) => params.speed >= *speed,
+        TransitionCondition::SpeedBelow(speed) => params.speed < *speed,
+        TransitionCondition::Grounded => params.grounded,
+        TransitionCondition::Airborne => !params.grounded,
+        TransitionCondition::Action(name) => params.action == Some(name.as_str()),
+        TransitionCondition::NoAction => params.action.is_none(),
+        TransitionCondition::Aiming => params.aiming,
+        TransitionCondition::NotAiming => !params.aiming,
+        TransitionCondition::Crouching => params.crouching,
+        TransitionCondition::NotCrouching => !params.crouching,
+        TransitionCondition::VerticalSpeedAbove(speed) => params.vertical_speed >= *speed,
+        TransitionCondition::VerticalSpeedBelow(speed) => params.vertical_speed < *speed,
+        TransitionCondition::JumpPhase(phase) => params.jump_phase == Some(*phase),
+        TransitionCondition::TurnAngleAbove(angle) => params.turn_angle >= *angle,
+        TransitionCondition::TurnAngleBelow(angle) => params.turn_angle < *angle,
+        TransitionCondition::ImpactAbove(speed) => params.impact_speed >= *speed,
+        TransitionCondition::ImpactBelow(speed) => params.impact_speed < *speed,
+        TransitionCondition::Finished => params.finished,
+    }
+}
+
+/// Returns how far a clip node has played, from 0.0 to 1.0
+fn clip_progress(
+    node: AnimationNodeIndex,
+    animation_player: &AnimationPlayer,
+    graph: &AnimationGraph,
+    clips: &Assets<AnimationClip>,
+) -> Option<f32> {
+    let duration = clip_duration(node, graph, clips)?;
+    let seek_time = animation_player.animation(node)?.seek_time();
+    Some(if duration > 0.0 {
+        (seek_time / duration).clamp(0.0, 1.0)
+    } else {
+        1.0
+    })
+}
+
+/// Returns the duration of the clip played by a node, if it is loaded
+pub fn clip_duration(
+    node: AnimationNodeIndex,
+    graph: &AnimationGraph,
+    clips: &Assets<AnimationClip>,
+) -> Option<f32> {
+    let AnimationNodeType::Clip(clip) = &graph.get(node)?.node_type else {
+        return None;
+    };
+    Some(clips.get(clip)?.duration())
+}
+
+/// Starts the clips of the given state
+///
+/// Looping clips keep their phase if they are still fading out, one-shot clips restart.
+fn play_state(
+    state_machine: &AnimationStateMachine,
     animation_nodes: &AnimationNodes,
+    blend_config: &AnimationBlendingConfig,
+    name: &str,
+    animation_player: &mut AnimationPlayer,
 ) {
-     let animating_directive = animating_state.update_by_discriminant(new_state);
+    let (Some(state), Some(nodes)) = (
+        state_machine.states.get(name),
+        animation_nodes.states.get(name),
+    ) else {
+        return;
+    };
 
-     match animating_directive {
-        TnuaAnimatingStateDirective::Maintain { state } => {
-            // info!("Maintained");
-            // `Maintain` means that we did not switch to a different variant, so there is no need
-            // to change animations.
+    for (&node, clip) in nodes.iter().zip(state.motion.clips(blend_config)) {
+        let animation = if state.looping {
+            animation_player.play(node).repeat()
+        } else {
+            animation_player.start(node)
+        };
+        animation.set_speed(state.speed * clip.playback_speed);
+    }
+}
 
-            // For the Moving state, even when the state variant remains the same, the speed can
-            // change. We need to update the blend weights to smoothly transition between walk and run.
+/// Sets the weight of every playing clip from its state's fade and its motion
+fn apply_state_weights(
+    state_machine: &AnimationStateMachine,
+    animation_nodes: &AnimationNodes,
+    blend_config: &AnimationBlendingConfig,
+    transitions: &StateTransitions,
+    params: &AnimationParameters,
+    animation_player: &mut AnimationPlayer,
+) {
+    for (name, state_weight) in transitions.weights() {
+        let (Some(state), Some(nodes)) = (
+            state_machine.states.get(name),
+            animation_nodes.states.get(name),
+        ) else {
+            continue;
+        };
 
-        }
-        TnuaAnimatingStateDirective::Alter {
-            old_state,
-            state,
-        } => {
-            // info!("Altered");
-            // `Alter` means that we have switched to a different variant and need to play a
-            // different animation.
-
-            // First - stop the currently running animation. We don't check which one is running
-            // here because we just assume it belongs to the old state, but more sophisticated code
-            // can try to phase from the old animation to the new one.
-            // animation_player.stop_all();
-
-            // Depending on the new state, we choose the animation to run and its parameters
-            match state {
-                AnimationState::Idle => {
-                    transitions.play(
-                        animation_player,
-                        animation_nodes.idle,
-                         Duration::from_millis(200)).repeat();
-                },
-                AnimationState::Walking => {
-                    transitions
-                    .play(
-                        animation_player,
-                        animation_nodes.walk,
-                        Duration::from_millis(200)).repeat();
-                },
-                AnimationState::Moving(_) => {
-                    transitions
-                    .play(
-                        animation_player,
-                        animation_nodes.run,
-                        Duration::from_millis(500)).repeat();
-                },
-                AnimationState::Running(_) => {
-                    transitions
-                    .play(
-                        animation_player,
-                        animation_nodes.run,
-                        Duration::from_millis(500))
-                        .repeat()
-                        .set_speed(1.2);
-                },
-                AnimationState::Jumping => {
-                    // Play appropriate jump animation based on previous state
-                    match old_state.unwrap() {
-                        AnimationState::Walking |
-                        AnimationState::Moving(_) |
-                        AnimationState::Running(_) => {
-                            // Running jump when jumping while moving
-                            transitions
-                                .play(
-                                    animation_player,
-                                    animation_nodes.running_jump,
-                                    Duration::from_millis(50))
-                                .set_speed(1.2);
-                            info!("Playing running jump (one-shot)");
-                        }
-                        AnimationState::Idle => {
-                            // Standing jump when jumping from idle
-                            transitions
-                                .play(
-                                    animation_player,
-                                    animation_nodes.jump,
-                                    Duration::from_millis(50));
-                            info!("Playing standing jump (one-shot)");
-                        }
-                        _ => {
-                            // Default to standing jump for any other state
-                            transitions
-                                .play(
-                                    animation_player,
-                                    animation_nodes.jump,
-                                    Duration::from_millis(50));
-                        }
-                    }
-                }
+        let clip_weights = match &state.motion {
+            StateMotion::Clip(_) => vec![1.0],
+            StateMotion::Locomotion => {
+                locomotion_weights(&blend_config.locomotion_samples(), params.speed)
+            }
+            StateMotion::Directional => {
+                directional_weights(&blend_config.directional, params.local_velocity)
+            }
+        };
+        for (&node, clip_weight) in nodes.iter().zip(clip_weights) {
+            if let Some(animation) = animation_player.animation_mut(node) {
+                animation.set_weight(state_weight * clip_weight);
             }
         }
     }
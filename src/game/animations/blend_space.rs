@@ -0,0 +1,207 @@
+//! Blend spaces that weight several clips from the character's movement.
+
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use bevy::prelude::*;
+
+use crate::game::configs::{DirectionalSample, LocomotionSample};
+
+/// Computes the weight of every sample of the 1D locomotion blend space at `speed`
+///
+/// `samples` must be sorted by speed. The two samples surrounding `speed` are linearly
+/// interpolated, and speeds outside the sampled range clamp to the first or last sample.
+pub fn locomotion_weights(samples: &[LocomotionSample], speed: f32) -> Vec<f32> {
+    let mut weights = vec![0.0; samples.len()];
+    if samples.is_empty() {
+        return weights;
+    }
+
+    // Index of the first sample faster than the current speed
+    let upper = samples
+        .iter()
+        .position(|sample| sample.speed > speed)
+        .unwrap_or(samples.len());
+
+    if upper == 0 {
+        weights[0] = 1.0;
+    } else if upper == samples.len() {
+        weights[upper - 1] = 1.0;
+    } else {
+        let (lower_speed, upper_speed) = (samples[upper - 1].speed, samples[upper].speed);
+        let factor = ((speed - lower_speed) / (upper_speed - lower_speed)).clamp(0.0, 1.0);
+        weights[upper - 1] = 1.0 - factor;
+        weights[upper] = factor;
+    }
+    weights
+}
+
+/// Computes the weight of every sample of the 2D directional blend space at `velocity`
+///
+/// `velocity` is relative to the character's facing, `x` pointing right and `y` forward. The two
+/// samples on the ring whose directions surround the velocity are interpolated by angle, and the
+/// center sample (at zero velocity) fades in as the speed drops below the ring.
+pub fn directional_weights(samples: &[DirectionalSample], velocity: Vec2) -> Vec<f32> {
+    let mut weights = vec![0.0; samples.len()];
+    let center = samples
+        .iter()
+        .position(|sample| sample.velocity().length_squared() < f32::EPSILON);
+
+    // Ring samples sorted by direction, counter-clockwise from the right
+    let mut ring: Vec<(usize, f32)> = samples
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| Some(*index) != center)
+        .map(|(index, sample)| (index, sample.velocity().to_angle()))
+        .collect();
+    ring.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    let standing = velocity.length_squared() < f32::EPSILON;
+    if ring.is_empty() || (standing && center.is_some()) {
+        if let Some(center) = center {
+            weights[center] = 1.0;
+        }
+        return weights;
+    }
+
+    // Without a center sample, standing still blends the ring as if moving forward
+    let angle = if standing {
+        FRAC_PI_2
+    } else {
+        velocity.to_angle()
+    };
+    let upper = ring
+        .iter()
+        .position(|(_, sample_angle)| *sample_angle >= angle)
+        .unwrap_or(0);
+    let lower = (upper + ring.len() - 1) % ring.len();
+    let ((lower_index, lower_angle), (upper_index, upper_angle)) = (ring[lower], ring[upper]);
+
+    let span = (upper_angle - lower_angle).rem_euclid(TAU);
+    let factor = if span > 0.0 {
+        ((angle - lower_angle).rem_euclid(TAU) / span).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    // Fraction of the ring's speed in this direction the character is moving at
+    let ring_speed = samples[lower_index]
+        .velocity()
+        .length()
+        .lerp(samples[upper_index].velocity().length(), factor);
+    let radial = if center.is_some() {
+        (velocity.length() / ring_speed).clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+
+    weights[lower_index] += (1.0 - factor) * radial;
+    weights[upper_index] += factor * radial;
+    if let Some(center) = center {
+        weights[center] = 1.0 - radial;
+    }
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locomotion(speeds: &[f32]) -> Vec<LocomotionSample> {
+        speeds
+            .iter()
+            .map(|&speed| LocomotionSample {
+                clip: String::new(),
+                speed,
+                playback_speed: 1.0,
+                sync: true,
+            })
+            .collect()
+    }
+
+    fn directional(velocities: &[(f32, f32)]) -> Vec<DirectionalSample> {
+        velocities
+            .iter()
+            .map(|&(right, forward)| DirectionalSample {
+                clip: String::new(),
+                right,
+                forward,
+                playback_speed: 1.0,
+                sync: true,
+            })
+            .collect()
+    }
+
+    fn assert_weights(weights: &[f32], expected: &[f32]) {
+        assert_eq!(weights.len(), expected.len());
+        for (weight, expected) in weights.iter().zip(expected) {
+            assert!(
+                (weight - expected).abs() < 1e-4,
+                "{weights:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn locomotion_interpolates_between_surrounding_samples() {
+        let samples = locomotion(&[0.0, 2.0, 6.0]);
+        assert_weights(&locomotion_weights(&samples, 1.0), &[0.5, 0.5, 0.0]);
+        assert_weights(&locomotion_weights(&samples, 5.0), &[0.0, 0.25, 0.75]);
+        assert_weights(&locomotion_weights(&samples, 2.0), &[0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn locomotion_clamps_outside_the_sampled_range() {
+        let samples = locomotion(&[1.0, 4.0]);
+        assert_weights(&locomotion_weights(&samples, 0.0), &[1.0, 0.0]);
+        assert_weights(&locomotion_weights(&samples, 10.0), &[0.0, 1.0]);
+        assert!(locomotion_weights(&[], 3.0).is_empty());
+    }
+
+    // Idle, forward, right, back and left
+    const RING: [(f32, f32); 5] = [(0.0, 0.0), (0.0, 4.0), (4.0, 0.0), (0.0, -4.0), (-4.0, 0.0)];
+
+    #[test]
+    fn directional_blends_neighbouring_ring_samples() {
+        let samples = directional(&RING);
+        assert_weights(
+            &directional_weights(&samples, Vec2::new(0.0, 4.0)),
+            &[0.0, 1.0, 0.0, 0.0, 0.0],
+        );
+        let diagonal = Vec2::new(1.0, 1.0).normalize() * 4.0;
+        assert_weights(
+            &directional_weights(&samples, diagonal),
+            &[0.0, 0.5, 0.5, 0.0, 0.0],
+        );
+        // Back left wraps around the ring between left and back
+        assert_weights(
+            &directional_weights(&samples, -diagonal),
+            &[0.0, 0.0, 0.0, 0.5, 0.5],
+        );
+    }
+
+    #[test]
+    fn directional_fades_in_the_center_when_slowing_down() {
+        let samples = directional(&RING);
+        assert_weights(
+            &directional_weights(&samples, Vec2::ZERO),
+            &[1.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert_weights(
+            &directional_weights(&samples, Vec2::new(0.0, 2.0)),
+            &[0.5, 0.5, 0.0, 0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn directional_without_center_stands_still_facing_forward() {
+        let samples = directional(&RING[1..]);
+        assert_weights(
+            &directional_weights(&samples, Vec2::ZERO),
+            &[1.0, 0.0, 0.0, 0.0],
+        );
+        assert_weights(
+            &directional_weights(&samples, Vec2::new(2.0, 0.0)),
+            &[0.0, 1.0, 0.0, 0.0],
+        );
+    }
+}
@@ -1,25 +1,61 @@
+use avian3d::prelude::*;
 use bevy::prelude::*;
-use bevy_tnua::{builtins::TnuaBuiltinDash, prelude::*};
+use bevy_tnua::{
+    builtins::{TnuaBuiltinClimb, TnuaBuiltinCrouch, TnuaBuiltinDash},
+    control_helpers::{TnuaCrouchEnforcer, TnuaSimpleAirActionsCounter},
+    prelude::*,
+};
 use bevy_hotpatching_experiments::hot;
-use crate::{game::player::{MovementController, Player}};
+use crate::{game::{configs::{AnimationBlendingConfig, ClimbConfig}, player::{CROUCH_FLOAT_OFFSET, DODGE, Dodge, Gait, MovementController, Parkour, ParkourAction, ParkourMove, Player, Stamina}, third_person_camera::ThirdPersonCamera}};
+
+use super::{layers::AnimationLayers, motion_warping::MotionWarp, root_motion::RootMotion};
 
 
 const FLOAT_HEIGHT: f32 = 0.9;
+/// Turning rate while an animation drives the facing, fast enough to follow the clip exactly
+const ANIMATED_TURNING_ANGVEL: f32 = 50.0;
 const ROTATION_SPEED: f32 = 10.0;
+/// Name of the jump action started in the air, so the state machine can play its own clip
+const AIR_JUMP: &str = "air_jump";
 
 #[hot]
 pub fn apply_controls(
     keyboard: Res<ButtonInput<KeyCode>>,
-    mut query: Query<&mut TnuaController>,
-    mut movement_query: Query<(&MovementController, &mut Transform), With<Player>>,
+    mut query: Query<
+        (
+            &mut TnuaController,
+            &mut TnuaCrouchEnforcer,
+            &mut TnuaSimpleAirActionsCounter,
+        ),
+        With<Player>,
+    >,
+    mouse: Res<ButtonInput<MouseButton>>,
+    // `#[hot]` takes at most nine parameters, so the player's components share this query
+    mut movement_query: Query<
+        (
+            &mut MovementController,
+            &mut Transform,
+            &mut LinearVelocity,
+            Option<&RootMotion>,
+            Option<&Stamina>,
+            Option<&mut AnimationLayers>,
+            Option<&Dodge>,
+            Option<&mut Parkour>,
+            Option<&mut MotionWarp>,
+        ),
+        With<Player>,
+    >,
     camera_query: Query<&Transform, (With<Camera3d>, Without<Player>)>,
-    time: Res<Time>,
+    third_person_camera_query: Query<&ThirdPersonCamera>,
+    gamepad_query: Query<&Gamepad>,
+    gravity: Res<Gravity>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
 ) {
-    let Ok(mut controller) = query.single_mut() else {
+    let Ok((mut controller, mut crouch_enforcer, mut air_actions)) = query.single_mut() else {
         return;
     };
 
-    let Ok((movement_controller, mut player_transform)) = movement_query.single_mut() else {
+    let Ok((mut movement_controller, mut player_transform, mut linear_velocity, root_motion, stamina, layers, dodge, parkour, mut motion_warp)) = movement_query.single_mut() else {
         return;
     };
 
@@ -50,50 +86,287 @@ pub fn apply_controls(
         direction += cam_right;
     }
 
-    // Determine speed based on whether Shift is pressed (run) or not (walk)
-    // let is_running = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
-    // let current_speed = if is_running {
-    //     movement_controller.run_speed
-    // } else {
-    //     movement_controller.walk_speed
-    // };
+    // While aiming the player keeps facing the camera and strafes, otherwise it turns towards
+    // where it's going
+    movement_controller.is_aiming = third_person_camera_query
+        .single()
+        .is_ok_and(|cam| cam.is_aiming(&mouse, gamepad_query.single().ok()));
+    // The animation compares this with the facing to turn in place or pivot
+    movement_controller.input_direction = if movement_controller.is_aiming {
+        Vec3::ZERO
+    } else {
+        direction.normalize_or_zero()
+    };
+
+    // Run by default, walk while Alt is held and sprint while Shift is held and there is stamina
+    // left. Sprinting is only possible moving freely, so not while aiming, crouched or locked.
+    let sprint_pressed = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let walk_pressed = keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    let can_sprint = stamina.is_none_or(Stamina::can_sprint)
+        && direction != Vec3::ZERO
+        && !movement_controller.is_crouching
+        && !movement_controller.is_aiming
+        && !movement_controller.movement_locked;
+    movement_controller.gait = if sprint_pressed && can_sprint {
+        Gait::Sprint
+    } else if walk_pressed {
+        Gait::Walk
+    } else {
+        Gait::Run
+    };
+    let speed = if movement_controller.is_crouching {
+        movement_controller.crouch_speed
+    } else {
+        match movement_controller.gait {
+            Gait::Walk => movement_controller.walk_speed,
+            Gait::Run => movement_controller.run_speed,
+            Gait::Sprint => movement_controller.run_speed * movement_controller.sprint_multiplier,
+        }
+    };
+    let mut desired_velocity = direction.normalize_or_zero() * speed;
+    let mut turning_angvel = 12.0; // Increased for more responsive turning.
+    let mut desired_forward = if movement_controller.is_aiming {
+        Dir3::new(cam_forward).ok()
+    } else {
+        Dir3::new(direction.normalize_or_zero()).ok()
+    };
+
+    // Some animations, like a hard landing, keep the player in place until they end
+    if movement_controller.movement_locked {
+        desired_velocity = Vec3::ZERO;
+        desired_forward = None;
+    }
+
+    // Turning animations rotate the character in sync with the clip
+    if let Some(forward) = movement_controller.animated_forward {
+        desired_forward = Some(forward);
+        turning_angvel = ANIMATED_TURNING_ANGVEL;
+    }
+
+    // Root motion clips move the character themselves, the input is ignored until they end
+    if let Some(root_motion) = root_motion.filter(|root_motion| root_motion.active) {
+        desired_velocity = root_motion.velocity;
+        desired_forward = None;
+    }
+
+    let jump_pressed = keyboard.pressed(KeyCode::Space) && !movement_controller.movement_locked;
+    let fresh_press = jump_pressed && !movement_controller.jump_held;
+    movement_controller.jump_held = jump_pressed;
+    let airborne = controller.is_airborne().unwrap_or(false);
+    let crouch_pressed = keyboard.any_pressed([KeyCode::KeyC, KeyCode::ControlLeft]);
+
+    // The dash carries on by itself once started, so it's only fed on a fresh press. Dodges
+    // don't cut into warped moves and hangs, which place the character themselves.
+    let dodge_pressed = keyboard.pressed(KeyCode::KeyQ) && !movement_controller.movement_locked;
+    let fresh_dodge = dodge_pressed && !movement_controller.dodge_held;
+    movement_controller.dodge_held = dodge_pressed;
+    let warping = motion_warp.as_ref().is_some_and(|motion_warp| motion_warp.is_active());
+    let hanging = parkour
+        .as_ref()
+        .and_then(|parkour| parkour.target)
+        .is_some_and(|target| target.action == ParkourAction::Hang);
+    let dodge = dodge.filter(|dodge| fresh_dodge && dodge.is_ready() && !warping && !hanging);
+    // Until it's over the dodge takes priority over parkour moves, jumps and crouching, which
+    // would replace the dash as the controller's action
+    let dodging = dodge.is_some() || controller.action_name() == Some(DODGE);
+
+    // Space on the ground starts the parkour move over the obstacle ahead, if there is one. The
+    // move runs until its action ends, whether Space is still held or not. Jumping up to hang
+    // from a wall holds on to its top until Space climbs up or crouching lets go.
+    let mut parkour_move = None;
+    if let Some(mut parkour) = parkour {
+        let mut started = false;
+        if hanging && fresh_press {
+            parkour.target = parkour.target.map(|target| ParkourMove {
+                action: ParkourAction::ClimbUp,
+                ..target
+            });
+            started = true;
+        } else if hanging && crouch_pressed {
+            parkour.target = None;
+        } else if fresh_press && !airborne && !movement_controller.is_crouching && !dodging {
+            parkour.target = parkour.plan(gravity.0.length()).map(|mut parkour_move| {
+                // Launch at least fast enough to get across, keeping any extra speed
+                let horizontal = Vec3::new(linear_velocity.x, 0.0, linear_velocity.z);
+                let speed = horizontal.length().max(parkour_move.velocity.length());
+                parkour_move.velocity = parkour_move.obstacle.direction * speed;
+                parkour_move
+            });
+            started = true;
+        } else if dodging
+            || parkour
+                .target
+                .is_some_and(|target| controller.action_name() != Some(target.action.name()))
+        {
+            // Cut short by a dodge, over, or the action never started
+            parkour.target = None;
+        } else if let Some(target) = parkour
+            .target
+            .filter(|target| target.action == ParkourAction::JumpToHang && !warping)
+        {
+            // Up at the top edge, hang from it
+            parkour.target = Some(ParkourMove {
+                action: ParkourAction::Hang,
+                velocity: Vec3::ZERO,
+                ..target
+            });
+        }
+
+        // Vaults and climbs are warped onto the obstacle instead of jumped, if there is somewhere
+        // to land
+        if let Some(warped) = parkour
+            .target
+            .filter(|parkour_move| started && parkour_move.action.is_warped())
+        {
+            let path = blend_config.as_ref().and_then(|config| {
+                warped.warp_path(player_transform.translation, FLOAT_HEIGHT, config)
+            });
+            let warp_started = path
+                .zip(motion_warp.as_mut())
+                .is_some_and(|(path, motion_warp)| motion_warp.start(path, warped.exit_velocity()));
+            if !warp_started {
+                parkour.target = None;
+            }
+        }
+        // Only a move that is going ahead launches the player
+        if let Some(launch) = parkour.target.filter(|_| started) {
+            linear_velocity.x = launch.velocity.x;
+            linear_velocity.z = launch.velocity.z;
+        }
+        parkour_move = parkour.target;
+    }
+    if let Some(parkour_move) = parkour_move {
+        desired_velocity = parkour_move.velocity;
+        desired_forward = Some(parkour_move.obstacle.direction);
+    }
 
     // Feed the basis every frame. Even if the player doesn't move - just use `desired_velocity:
     // Vec3::ZERO`. `TnuaController` starts without a basis, which will make the character collider
     // just fall.
     controller.basis(TnuaBuiltinWalk {
         // The `desired_velocity` determines how the character will move.
-        desired_velocity: direction.normalize_or_zero() * movement_controller.run_speed,
+        desired_velocity,
         // The `float_height` must be greater (even if by little) from the distance between the
         // character's center and the lowest point of its collider.
         float_height: FLOAT_HEIGHT,
-        turning_angvel: 12.0,  // Increased for more responsive turning.
-        desired_forward: Dir3::new(direction.normalize_or_zero()).ok(),
+        turning_angvel,
+        desired_forward,
         // `TnuaBuiltinWalk` has many other fields for customizing the movement - but they have
         // sensible defaults. Refer to the `TnuaBuiltinWalk`'s documentation to learn what they do.
         ..Default::default()
     });
 
 
-    if keyboard.pressed(KeyCode::Space) {
-        // Disabling jump for now
-        // space button will trigger parkour actions based on environment detection
-        // controller.action(TnuaBuiltinJump {
-        //     // The height is the only mandatory field of the jump button.
-        //     height: movement_controller.jump_height,
-        //     input_buffer_time: 0.5,
-        //     // `TnuaBuiltinJump` also has customization fields with sensible defaults.
-        //     ..Default::default()
-        // });
-        controller.named_action("jump",
-            TnuaBuiltinJump {
-            // The height is the only mandatory field of the jump button.
-            height: movement_controller.jump_height,
-            input_buffer_time: 0.5,
-            // `TnuaBuiltinJump` also has customization fields with sensible defaults.
+    // Crouch while C or Ctrl is held. The enforcer keeps feeding the crouch under obstacles, so
+    // the player only stands up where there is room.
+    if crouch_pressed && !movement_controller.movement_locked && !dodging {
+        controller.action(crouch_enforcer.enforcing(TnuaBuiltinCrouch {
+            float_offset: CROUCH_FLOAT_OFFSET,
+            ..Default::default()
+        }));
+    }
+
+    // Dodge with Q towards the input, or where the camera looks without input
+    if let Some(dodge) = dodge {
+        let dodge_direction = if direction == Vec3::ZERO {
+            cam_forward
+        } else {
+            direction.normalize()
+        };
+        controller.named_action(DODGE, TnuaBuiltinDash {
+            displacement: dodge_direction * dodge.distance,
+            // While aiming the player keeps facing the camera and dodges sideways
+            desired_forward: if movement_controller.is_aiming {
+                None
+            } else {
+                Dir3::new(dodge_direction).ok()
+            },
+            allow_in_air: dodge.allow_in_air,
+            speed: dodge.speed,
             ..Default::default()
+        });
+    }
+
+    // Wave over the current movement while G is held
+    if let Some(mut layers) = layers {
+        layers.set_active("wave", keyboard.pressed(KeyCode::KeyG));
+    }
+
+    // Tnua counts the jumps since leaving the ground, it has to see every update
+    air_actions.update(&controller);
+    movement_controller.double_jump_available =
+        air_actions.air_count_for(AIR_JUMP) <= movement_controller.air_jumps;
+
+    // Air jumps redirect only once Tnua has started them, so a refused one leaves the momentum
+    let air_jump_started = controller.action_flow_status().just_starting() == Some(AIR_JUMP);
+    if air_jump_started && direction != Vec3::ZERO {
+        // Turn the horizontal velocity toward the input, keeping its speed
+        let horizontal = Vec3::new(linear_velocity.x, 0.0, linear_velocity.z);
+        let redirected = direction.normalize() * horizontal.length();
+        let velocity = horizontal.lerp(redirected, movement_controller.air_jump_redirect);
+        linear_velocity.x = velocity.x;
+        linear_velocity.z = velocity.z;
+    }
+
+    if let Some(parkour_move) = parkour_move {
+        // The state machine plays the clip of the move by the action's name
+        let obstacle = parkour_move.obstacle;
+        if parkour_move.action.is_warped() {
+            // Tnua only senses while the move is warped, so the action does nothing but name
+            // the move. Once the warp is over it's no longer fed and ends.
+            if motion_warp.is_some_and(|motion_warp| motion_warp.is_active()) {
+                controller.named_action(parkour_move.action.name(), TnuaBuiltinClimb {
+                    anchor: obstacle.edge,
+                    desired_forward: Some(obstacle.direction),
+                    coyote_time: 0.0,
+                    ..Default::default()
+                });
+            }
+        } else if parkour_move.action == ParkourAction::Hang {
+            // Held in place below the edge, where the jump up left the character
+            let hang_offset = blend_config
+                .as_ref()
+                .map_or(ClimbConfig::default().hang_offset, |config| config.climb.hang_offset);
+            controller.named_action(parkour_move.action.name(), TnuaBuiltinClimb {
+                anchor: obstacle.edge,
+                desired_vec_to_anchor: obstacle.direction * hang_offset,
+                desired_forward: Some(obstacle.direction),
+                coyote_time: 0.0,
+                ..Default::default()
+            });
+        } else {
+            controller.named_action(parkour_move.action.name(), TnuaBuiltinJump {
+                height: parkour_move.jump_height,
+                ..Default::default()
+            });
+        }
+    } else if jump_pressed && !dodging {
+        // A fresh press in the air is an air jump, which keeps its name for as long as the
+        // button is held so that Tnua sees a single action
+        let air_jump = if fresh_press {
+            airborne
+        } else {
+            controller.action_name() == Some(AIR_JUMP)
+        };
+
+        if air_jump {
+            controller.named_action(AIR_JUMP, TnuaBuiltinJump {
+                height: movement_controller.air_jump_height,
+                allow_in_air: movement_controller.double_jump_available,
+                ..Default::default()
+            });
+        } else {
+            // Nothing to get over ahead, a plain jump
+            controller.named_action("jump",
+                TnuaBuiltinJump {
+                // The height is the only mandatory field of the jump button.
+                height: movement_controller.jump_height,
+                input_buffer_time: 0.5,
+                // `TnuaBuiltinJump` also has customization fields with sensible defaults.
+                ..Default::default()
+            }
+            );
         }
-        );
     }
 
 }
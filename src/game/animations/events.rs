@@ -0,0 +1,53 @@
+//! Timed event markers on animation clips.
+
+use bevy::prelude::*;
+
+use crate::game::configs::AnimationMarker;
+
+/// Triggered on the [`AnimationPlayer`] entity when a playing clip crosses one of its markers
+///
+/// Every clip of a blend fires its own markers, so observers should check `weight` to ignore
+/// clips that are fading out or barely contribute to the pose.
+#[derive(EntityEvent, Debug, Clone)]
+pub struct AnimationMarkerReached {
+    /// The entity with the [`AnimationPlayer`]
+    pub entity: Entity,
+    /// Name of the marker from the [`AnimationBlendingConfig`](crate::game::configs::AnimationBlendingConfig)
+    pub marker: String,
+    /// Weight of the clip when the marker was crossed
+    pub weight: f32,
+}
+
+/// Returns a copy of `clip` that fires [`AnimationMarkerReached`] at every marker
+///
+/// The source clip is shared with every other user of the GLTF, so it is left untouched. Clips
+/// without markers, or that are not loaded yet, are returned as-is.
+pub fn clip_with_markers(
+    clips: &mut Assets<AnimationClip>,
+    clip: &Handle<AnimationClip>,
+    markers: &[AnimationMarker],
+) -> Handle<AnimationClip> {
+    if markers.is_empty() {
+        return clip.clone();
+    }
+    let Some(source) = clips.get(clip) else {
+        return clip.clone();
+    };
+
+    let mut marked = source.clone();
+    let duration = marked.duration();
+    for marker in markers {
+        let name = marker.name.clone();
+        marked.add_event_fn(
+            marker.phase.clamp(0.0, 1.0) * duration,
+            move |commands, entity, _time, weight| {
+                commands.trigger(AnimationMarkerReached {
+                    entity,
+                    marker: name.clone(),
+                    weight,
+                });
+            },
+        );
+    }
+    clips.add(marked)
+}
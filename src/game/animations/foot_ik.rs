@@ -0,0 +1,279 @@
+//! Foot placement on uneven ground with analytic two-bone leg IK.
+//!
+//! The legs are expected to be animated every frame, as IK only offsets the sampled pose and
+//! would otherwise accumulate.
+//!
+//! The solver is written here instead of using `bevy_mod_inverse_kinematics`, whose latest
+//! release is built against Bevy 0.16 and can't work with this project's Bevy 0.17 types.
+
+use avian3d::prelude::*;
+use bevy::{app::AnimationSystems, prelude::*, transform::TransformSystems};
+use bevy_tnua::prelude::*;
+
+use super::skeleton::{Bones, find_bone, parent_world_transform, world_transform};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        PostUpdate,
+        (resolve_foot_ik_rigs, place_feet)
+            .chain()
+            .after(AnimationSystems)
+            .before(TransformSystems::Propagate),
+    );
+}
+
+/// Places the feet of a character on the ground below them
+///
+/// Add to the character root, next to its [`SceneRoot`] child. Bone names default to the
+/// Mixamo rig.
+#[derive(Component, Debug, Clone)]
+pub struct FootIk {
+    /// Turns foot placement on or off, the IK fades in and out over `blend_speed`
+    pub enabled: bool,
+    /// Name of the pelvis bone, lowered when a foot has to reach below the character
+    pub hips: String,
+    /// Bones of the left and right leg
+    pub legs: [LegBones; 2],
+    /// Furthest the feet are moved up or down to reach the ground
+    pub max_step: f32,
+    /// Animated foot lift above the ground at which the IK weight reaches zero
+    pub swing_height: f32,
+    /// How fast the planted height of a foot drifts back up to the foot's current height, per
+    /// second, so a low pose like a crouch or a roll doesn't lower it for good
+    pub rest_recovery: f32,
+    /// How fast weights and the pelvis offset follow their targets, per second
+    pub blend_speed: f32,
+}
+
+/// Bone names of a leg, from the hip down
+#[derive(Debug, Clone)]
+pub struct LegBones {
+    pub upper: String,
+    pub lower: String,
+    pub foot: String,
+}
+
+impl LegBones {
+    fn mixamo(side: &str) -> Self {
+        Self {
+            upper: format!("mixamorig:{side}UpLeg"),
+            lower: format!("mixamorig:{side}Leg"),
+            foot: format!("mixamorig:{side}Foot"),
+        }
+    }
+}
+
+impl Default for FootIk {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hips: "mixamorig:Hips".to_string(),
+            legs: [LegBones::mixamo("Left"), LegBones::mixamo("Right")],
+            max_step: 0.5,
+            swing_height: 0.25,
+            rest_recovery: 0.5,
+            blend_speed: 10.0,
+        }
+    }
+}
+
+/// Bone entities of a [`FootIk`] character and the smoothed IK state
+#[derive(Component, Debug)]
+pub(super) struct FootIkRig {
+    model: Entity,
+    hips: Entity,
+    legs: [[Entity; 3]; 2],
+    /// Lowest recent animated height of each foot above the model's ground, its height when
+    /// planted
+    rest_heights: [f32; 2],
+    weights: [f32; 2],
+    pelvis_offset: f32,
+}
+
+/// Finds the bones of characters whose scene has spawned
+fn resolve_foot_ik_rigs(
+    mut commands: Commands,
+    characters: Query<(Entity, &FootIk, &Children), Without<FootIkRig>>,
+    models: Query<Entity, With<SceneRoot>>,
+    descendants: Query<&Children>,
+    names: Query<&Name>,
+) {
+    'characters: for (character, foot_ik, children) in &characters {
+        let Some(model) = children.iter().find(|child| models.contains(*child)) else {
+            continue;
+        };
+        let find = |bone: &str| find_bone(model, bone, &descendants, &names);
+
+        let Some(hips) = find(&foot_ik.hips) else {
+            continue;
+        };
+        let mut legs = [[Entity::PLACEHOLDER; 3]; 2];
+        for (leg, bones) in legs.iter_mut().zip(&foot_ik.legs) {
+            let (Some(upper), Some(lower), Some(foot)) =
+                (find(&bones.upper), find(&bones.lower), find(&bones.foot))
+            else {
+                // The scene may not have spawned yet
+                continue 'characters;
+            };
+            *leg = [upper, lower, foot];
+        }
+
+        commands.entity(character).insert(FootIkRig {
+            model,
+            hips,
+            legs,
+            rest_heights: [f32::MAX; 2],
+            weights: [0.0; 2],
+            pelvis_offset: 0.0,
+        });
+    }
+}
+
+/// Moves the feet onto the ground below them and lowers the pelvis to keep them in reach
+pub(super) fn place_feet(
+    mut characters: Query<(Entity, &FootIk, &mut FootIkRig, Option<&TnuaController>)>,
+    mut bones: Bones,
+    spatial_query: SpatialQuery,
+    time: Res<Time>,
+) {
+    for (character, foot_ik, mut rig, controller) in &mut characters {
+        let model = world_transform(rig.model, &bones);
+        let ground_height = model.translation.y;
+        let up = Vec3::Y;
+        let filter = SpatialQueryFilter::from_excluded_entities([character]);
+        let airborne =
+            controller.is_some_and(|controller| controller.is_airborne().unwrap_or(false));
+        let blend = (foot_ik.blend_speed * time.delta_secs()).min(1.0);
+
+        // Find how far each foot has to move to stand on the ground below it
+        let mut offsets = [0.0; 2];
+        let mut normals = [up; 2];
+        for side in 0..2 {
+            let foot = world_transform(rig.legs[side][2], &bones).translation;
+            let lift = foot.y - ground_height;
+            let recovery = (foot_ik.rest_recovery * time.delta_secs()).min(1.0);
+            rig.rest_heights[side] = rig.rest_heights[side].lerp(lift, recovery).min(lift);
+
+            // Feet lifted by the animation are swinging and only follow the ground partially
+            let swing = ((lift - rig.rest_heights[side]) / foot_ik.swing_height).clamp(0.0, 1.0);
+            let target_weight = if foot_ik.enabled && !airborne {
+                1.0 - swing
+            } else {
+                0.0
+            };
+            rig.weights[side] = rig.weights[side].lerp(target_weight, blend);
+
+            let origin = Vec3::new(foot.x, ground_height + foot_ik.max_step, foot.z);
+            if let Some(hit) =
+                spatial_query.cast_ray(origin, Dir3::NEG_Y, foot_ik.max_step * 2.0, true, &filter)
+            {
+                offsets[side] = (foot_ik.max_step - hit.distance) * rig.weights[side];
+                normals[side] = up.lerp(hit.normal, rig.weights[side]).normalize_or(up);
+            }
+        }
+
+        // The character floats on the highest ground, so only a lower foot needs the pelvis down
+        let target_pelvis = offsets[0].min(offsets[1]).min(0.0);
+        rig.pelvis_offset = rig.pelvis_offset.lerp(target_pelvis, blend);
+        if rig.pelvis_offset.abs() < 1e-4 && offsets.iter().all(|offset| offset.abs() < 1e-4) {
+            continue;
+        }
+
+        let hips = world_transform(rig.hips, &bones);
+        let hips_parent = parent_world_transform(rig.hips, &bones);
+        if let Ok((mut transform, _)) = bones.get_mut(rig.hips) {
+            let lowered = hips.translation + up * rig.pelvis_offset;
+            transform.translation = hips_parent
+                .compute_affine()
+                .inverse()
+                .transform_point3(lowered);
+        }
+
+        let knee_axis = *model.right();
+        for side in 0..2 {
+            let [upper, lower, foot] = rig.legs[side];
+            let (upper_world, lower_world, foot_world) = (
+                world_transform(upper, &bones),
+                world_transform(lower, &bones),
+                world_transform(foot, &bones),
+            );
+            let target = foot_world.translation + up * (offsets[side] - rig.pelvis_offset);
+            let alignment = Quat::from_rotation_arc(up, normals[side]);
+
+            let rotations = solve_two_bone(upper_world, lower_world, foot_world, target, knee_axis);
+            let upper_rotation = rotations.0 * upper_world.rotation;
+
+            if let Ok((mut transform, _)) = bones.get_mut(upper) {
+                transform.rotation =
+                    (transform.rotation * upper_world.rotation.inverse() * upper_rotation)
+                        .normalize();
+            }
+            let lower_local = {
+                let Ok((transform, _)) = bones.get(lower) else {
+                    continue;
+                };
+                (transform.rotation
+                    * lower_world.rotation.inverse()
+                    * rotations.1
+                    * lower_world.rotation)
+                    .normalize()
+            };
+            if let Ok((mut transform, _)) = bones.get_mut(lower) {
+                transform.rotation = lower_local;
+            }
+
+            // Keep the animated foot orientation in world space, tilted to the ground
+            let lower_rotation = upper_rotation * lower_local;
+            if let Ok((mut transform, _)) = bones.get_mut(foot) {
+                transform.rotation =
+                    (lower_rotation.inverse() * alignment * foot_world.rotation).normalize();
+            }
+        }
+    }
+}
+
+/// Solves a two-bone chain so its end reaches `target`
+///
+/// Returns the world-space rotation to apply to the upper bone, and the world-space rotation to
+/// apply to the lower bone before its parent moves. The chain keeps bending in its current plane,
+/// `bend_axis` is only used when the chain is fully straight.
+/// Based on <https://theorangeduck.com/page/simple-two-joint>.
+fn solve_two_bone(
+    upper: Transform,
+    lower: Transform,
+    end: Transform,
+    target: Vec3,
+    bend_axis: Vec3,
+) -> (Quat, Quat) {
+    let (a, b, c) = (upper.translation, lower.translation, end.translation);
+    let upper_length = a.distance(b);
+    let lower_length = b.distance(c);
+    let target_length = a
+        .distance(target)
+        .clamp(1e-3, upper_length + lower_length - 1e-3);
+
+    let angle = |from: Vec3, to: Vec3| from.angle_between(to);
+    let cosine_angle = |cosine: f32| cosine.clamp(-1.0, 1.0).acos();
+
+    // Current and wanted angles at the hip and the knee
+    let hip_angle = angle(c - a, b - a);
+    let knee_angle = angle(a - b, c - b);
+    let hip_target = cosine_angle(
+        (lower_length.powi(2) - upper_length.powi(2) - target_length.powi(2))
+            / (-2.0 * upper_length * target_length),
+    );
+    let knee_target = cosine_angle(
+        (target_length.powi(2) - upper_length.powi(2) - lower_length.powi(2))
+            / (-2.0 * upper_length * lower_length),
+    );
+
+    let bend = (c - a).cross(b - a).try_normalize().unwrap_or(bend_axis);
+    let swing_axis = (c - a).cross(target - a).try_normalize();
+    let swing = swing_axis.map_or(Quat::IDENTITY, |axis| {
+        Quat::from_axis_angle(axis, angle(c - a, target - a))
+    });
+
+    let hip = Quat::from_axis_angle(bend, hip_target - hip_angle);
+    let knee = Quat::from_axis_angle(bend, knee_target - knee_angle);
+    (swing * hip, knee)
+}
@@ -0,0 +1,54 @@
+//! Footstep sounds played on "footstep" animation markers.
+
+use bevy::prelude::*;
+use rand::seq::IndexedRandom;
+
+use crate::audio::sound_effect;
+
+use super::events::AnimationMarkerReached;
+
+/// Name of the marker placed where a foot plants in locomotion clips
+pub const FOOTSTEP_MARKER: &str = "footstep";
+
+/// Clip weight a marker needs to play a step
+///
+/// Blend weights add up to one, so only the clip dominating the pose plays its steps. Otherwise
+/// a walk clip fading out under a run would double every footstep. At an exactly even blend both
+/// clips play theirs, which is better than a stretch of speeds without any steps.
+const FOOTSTEP_MIN_WEIGHT: f32 = 0.5;
+
+pub(super) fn plugin(app: &mut App) {
+    // Not tracked by the loading screen, a step sound failing to load must not block gameplay
+    app.init_resource::<FootstepAssets>();
+    app.add_observer(play_footstep);
+}
+
+/// Step sounds, one of which is picked at random for each footstep
+#[derive(Resource)]
+pub struct FootstepAssets {
+    pub steps: Vec<Handle<AudioSource>>,
+}
+
+impl FromWorld for FootstepAssets {
+    fn from_world(world: &mut World) -> Self {
+        let assets = world.resource::<AssetServer>();
+        Self {
+            steps: (1..=4)
+                .map(|index| assets.load(format!("audio/sound_effects/step{index}.ogg")))
+                .collect(),
+        }
+    }
+}
+
+fn play_footstep(
+    marker: On<AnimationMarkerReached>,
+    mut commands: Commands,
+    footsteps: Res<FootstepAssets>,
+) {
+    if marker.marker != FOOTSTEP_MARKER || marker.weight < FOOTSTEP_MIN_WEIGHT {
+        return;
+    }
+    if let Some(step) = footsteps.steps.choose(&mut rand::rng()) {
+        commands.spawn((Name::new("Footstep"), sound_effect(step.clone())));
+    }
+}
@@ -0,0 +1,203 @@
+//! Inertialized transitions between animation states.
+//!
+//! Instead of evaluating the old and new state together during a cross-fade, the new state plays
+//! at full weight right away and the difference between the last output pose and the new pose is
+//! added on top, decaying to zero over the blend duration. The offset follows the quintic curve
+//! from David Bollo's "Inertialization: High-Performance Animation Transitions in Gears of War",
+//! which keeps the velocity of the old pose at the switch and comes to rest at the end.
+
+use std::collections::HashMap;
+
+use bevy::{
+    animation::AnimationTarget,
+    app::AnimationSystems,
+    prelude::*,
+    transform::TransformSystems,
+};
+
+use super::{foot_ik::place_feet, root_motion::extract_root_motion};
+
+pub(super) fn plugin(app: &mut App) {
+    // After root motion, so the offsets don't move the character, and before IK adjusts the
+    // inertialized pose to the ground
+    app.add_systems(
+        PostUpdate,
+        apply_inertialization
+            .after(AnimationSystems)
+            .after(extract_root_motion)
+            .before(place_feet)
+            .before(TransformSystems::Propagate),
+    );
+}
+
+/// Inertialization state of an [`AnimationPlayer`]'s bones
+#[derive(Component, Default, Debug)]
+pub struct Inertialization {
+    /// Duration of a transition started since the last evaluated pose
+    pending: Option<f32>,
+    /// Output pose of the previous frame
+    previous: HashMap<Entity, Transform>,
+    /// Output pose of the frame before that, for the velocity at the switch
+    before_previous: HashMap<Entity, Transform>,
+    previous_delta: f32,
+    offsets: HashMap<Entity, BoneOffset>,
+    elapsed: f32,
+}
+
+impl Inertialization {
+    /// Starts decaying the difference between the current pose and the next sampled pose over
+    /// `duration` seconds
+    pub fn start(&mut self, duration: f32) {
+        self.pending = Some(duration);
+    }
+}
+
+/// Offset of a bone from its sampled pose, along a fixed axis
+#[derive(Debug)]
+struct BoneOffset {
+    translation_axis: Vec3,
+    translation: OffsetCurve,
+    rotation_axis: Vec3,
+    rotation: OffsetCurve,
+}
+
+/// Quintic decay of a scalar offset to zero
+#[derive(Clone, Copy, Debug, Default)]
+struct OffsetCurve {
+    x0: f32,
+    v0: f32,
+    a0: f32,
+    /// Time at which the offset reaches zero
+    t1: f32,
+}
+
+impl OffsetCurve {
+    /// Fits a curve starting at `x0` with velocity `v0`, expected to be non-negative and
+    /// the velocity in the same direction
+    fn new(x0: f32, v0: f32, duration: f32) -> Self {
+        if x0 <= f32::EPSILON || duration <= 0.0 {
+            return Self::default();
+        }
+        // Moving away from the target pose would overshoot, so the velocity is dropped. Moving
+        // toward it too fast shortens the transition to avoid overshooting on the other side.
+        let v0 = v0.min(0.0);
+        let t1 = if v0 < 0.0 {
+            duration.min(-5.0 * x0 / v0)
+        } else {
+            duration
+        };
+        let a0 = ((-8.0 * v0 * t1 - 20.0 * x0) / t1.powi(2)).max(0.0);
+        Self { x0, v0, a0, t1 }
+    }
+
+    fn sample(&self, t: f32) -> f32 {
+        if t >= self.t1 {
+            return 0.0;
+        }
+        let Self { x0, v0, a0, t1 } = *self;
+        let a = -(a0 * t1.powi(2) + 6.0 * v0 * t1 + 12.0 * x0) / (2.0 * t1.powi(5));
+        let b = (3.0 * a0 * t1.powi(2) + 16.0 * v0 * t1 + 30.0 * x0) / (2.0 * t1.powi(4));
+        let c = -(3.0 * a0 * t1.powi(2) + 12.0 * v0 * t1 + 20.0 * x0) / (2.0 * t1.powi(3));
+        a * t.powi(5) + b * t.powi(4) + c * t.powi(3) + 0.5 * a0 * t.powi(2) + v0 * t + x0
+    }
+}
+
+impl BoneOffset {
+    /// Finds the offset from the sampled `current` pose to the last output pose
+    fn new(
+        current: &Transform,
+        previous: &Transform,
+        before_previous: Option<&Transform>,
+        delta: f32,
+        duration: f32,
+    ) -> Self {
+        let before_previous = before_previous.unwrap_or(previous);
+        let delta = delta.max(f32::EPSILON);
+
+        let offset = previous.translation - current.translation;
+        let translation_axis = offset.normalize_or_zero();
+        let translation_velocity =
+            (previous.translation - before_previous.translation).dot(translation_axis) / delta;
+
+        let (rotation_axis, angle) =
+            shortest(previous.rotation * current.rotation.inverse()).to_axis_angle();
+        let (velocity_axis, velocity_angle) =
+            shortest(previous.rotation * before_previous.rotation.inverse()).to_axis_angle();
+        let angular_velocity = (velocity_axis * velocity_angle).dot(rotation_axis) / delta;
+
+        Self {
+            translation_axis,
+            translation: OffsetCurve::new(offset.length(), translation_velocity, duration),
+            rotation_axis,
+            rotation: OffsetCurve::new(angle, angular_velocity, duration),
+        }
+    }
+
+    fn apply(&self, transform: &mut Transform, t: f32) {
+        transform.translation += self.translation_axis * self.translation.sample(t);
+        let angle = self.rotation.sample(t);
+        if angle != 0.0 && self.rotation_axis != Vec3::ZERO {
+            transform.rotation =
+                (Quat::from_axis_angle(self.rotation_axis, angle) * transform.rotation).normalize();
+        }
+    }
+}
+
+/// Returns the rotation going the short way around
+fn shortest(rotation: Quat) -> Quat {
+    if rotation.w < 0.0 {
+        -rotation
+    } else {
+        rotation
+    }
+}
+
+/// Adds the decaying transition offsets to the sampled pose and records the output pose
+pub(super) fn apply_inertialization(
+    mut players: Query<(Entity, &mut Inertialization)>,
+    mut bones: Query<(Entity, &AnimationTarget, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (player, mut inertialization) in &mut players {
+        let inertialization = &mut *inertialization;
+        if let Some(duration) = inertialization.pending.take() {
+            let delta = inertialization.previous_delta;
+            inertialization.offsets = bones
+                .iter()
+                .filter(|(_, target, _)| target.player == player)
+                .filter_map(|(bone, _, transform)| {
+                    let previous = inertialization.previous.get(&bone)?;
+                    let before_previous = inertialization.before_previous.get(&bone);
+                    Some((
+                        bone,
+                        BoneOffset::new(transform, previous, before_previous, delta, duration),
+                    ))
+                })
+                .collect();
+            inertialization.elapsed = 0.0;
+        } else {
+            inertialization.elapsed += time.delta_secs();
+        }
+
+        let elapsed = inertialization.elapsed;
+        inertialization
+            .offsets
+            .retain(|_, offset| elapsed < offset.translation.t1.max(offset.rotation.t1));
+
+        std::mem::swap(
+            &mut inertialization.previous,
+            &mut inertialization.before_previous,
+        );
+        inertialization.previous.clear();
+        for (bone, target, mut transform) in &mut bones {
+            if target.player != player {
+                continue;
+            }
+            if let Some(offset) = inertialization.offsets.get(&bone) {
+                offset.apply(&mut transform, elapsed);
+            }
+            inertialization.previous.insert(bone, *transform);
+        }
+        inertialization.previous_delta = time.delta_secs();
+    }
+}
@@ -0,0 +1,370 @@
+//! Partial-body animation layers played over the state machine.
+
+use std::collections::HashMap;
+
+use bevy::{
+    animation::{
+        AnimationEntityMut, AnimationTarget, VariableCurve, animated_field, graph::AnimationMask,
+    },
+    math::curve::UnevenSampleAutoCurve,
+    prelude::*,
+};
+
+use crate::game::configs::AnimationStateMachine;
+
+use super::{animation_controller::AnimationNodes, models::CharacterAnimationController};
+
+/// Mask group of every bone outside the named groups of the state machine
+const REST_MASK_GROUP: u32 = 63;
+
+/// Highest weight a layer is driven to, see [`layer_node_weight`]
+const MAX_LAYER_WEIGHT: f32 = 0.99;
+
+/// Frames per second the offsets of [`additive_clip`] are sampled at
+const ADDITIVE_SAMPLE_RATE: f32 = 30.0;
+
+/// Layers of the [`AnimationStateMachine`] playing on a character, e.g. waving while running
+///
+/// Gameplay code turns layers on and off, the animation fades them in and out.
+#[derive(Component, Default, Debug)]
+pub struct AnimationLayers {
+    layers: HashMap<String, LayerState>,
+}
+
+#[derive(Default, Debug)]
+struct LayerState {
+    active: bool,
+    weight: f32,
+}
+
+impl AnimationLayers {
+    /// Fades the layer in or out
+    pub fn set_active(&mut self, layer: &str, active: bool) {
+        if let Some(state) = self.layers.get_mut(layer) {
+            state.active = active;
+        } else if active {
+            self.layers.insert(
+                layer.to_string(),
+                LayerState {
+                    active,
+                    weight: 0.0,
+                },
+            );
+        }
+    }
+}
+
+/// Assigns every animated bone below `animation_player` to a mask group
+///
+/// Returns the index of every named group of the state machine. Bones outside all named groups
+/// go to [`REST_MASK_GROUP`], so that a layer masking everything but its group leaves them alone.
+pub fn build_mask_groups(
+    graph: &mut AnimationGraph,
+    state_machine: &AnimationStateMachine,
+    animation_player: Entity,
+    targets: &Query<(Entity, &AnimationTarget)>,
+    names: &Query<&Name>,
+    parents: &Query<&ChildOf>,
+) -> HashMap<String, u32> {
+    let mut group_names: Vec<_> = state_machine.mask_groups.keys().cloned().collect();
+    group_names.sort();
+    let groups: HashMap<String, u32> = group_names.into_iter().zip(0..).collect();
+
+    let bone_groups: HashMap<&str, u32> = state_machine
+        .mask_groups
+        .iter()
+        .flat_map(|(group, bones)| {
+            let index = groups[group];
+            bones.iter().map(move |bone| (bone.as_str(), index))
+        })
+        .collect();
+
+    for (entity, target) in targets {
+        if target.player != animation_player {
+            continue;
+        }
+        // The nearest listed ancestor (or the bone itself) decides the group
+        let group = std::iter::once(entity)
+            .chain(parents.iter_ancestors(entity))
+            .find_map(|bone| {
+                names
+                    .get(bone)
+                    .ok()
+                    .and_then(|name| bone_groups.get(name.as_str()))
+            })
+            .copied()
+            .unwrap_or(REST_MASK_GROUP);
+        graph.add_target_to_mask_group(target.id, group);
+    }
+    groups
+}
+
+/// Returns the mask for a layer node that only animates `group`
+pub fn layer_mask(group: u32) -> AnimationMask {
+    !(1 << group)
+}
+
+/// Returns a copy of `clip` holding every bone's offset from its pose in the first frame of
+/// `reference`, for layers that add their clip on top of the pose
+///
+/// Clips hold full poses, which an additive blend would stack onto the pose below instead of
+/// just moving it. Bones that `reference` doesn't animate, or all bones without a reference, are
+/// measured against the first frame of `clip` itself. Clips that are not loaded yet are returned
+/// as-is.
+pub fn additive_clip(
+    clips: &mut Assets<AnimationClip>,
+    clip: &Handle<AnimationClip>,
+    reference: Option<&Handle<AnimationClip>>,
+) -> Handle<AnimationClip> {
+    let Some(source) = clips.get(clip) else {
+        return clip.clone();
+    };
+    let reference = reference.and_then(|reference| clips.get(reference));
+
+    let duration = source.duration();
+    let frames = ((duration * ADDITIVE_SAMPLE_RATE).ceil() as usize).max(1);
+    let times: Vec<f32> = (0..=frames)
+        .map(|frame| duration * frame as f32 / frames as f32)
+        .collect();
+    let mut sampler = PoseSampler::new();
+    let mut additive = AnimationClip::default();
+    for (target, curves) in source.curves() {
+        let reference_curves = reference
+            .and_then(|reference| reference.curves_for_target(*target))
+            .unwrap_or(curves);
+        let rest = sampler.sample(reference_curves, 0.0);
+        let offsets: Vec<(f32, Transform)> = times
+            .iter()
+            .map(|&time| {
+                let pose = sampler.sample(curves, time);
+                // Additive rotations are applied before the pose below, like a parent rotation
+                let offset = Transform {
+                    translation: pose.translation - rest.translation,
+                    rotation: pose.rotation * rest.rotation.inverse(),
+                    scale: pose.scale - rest.scale,
+                };
+                (time, offset)
+            })
+            .collect();
+
+        // Zero length clips can't be sampled into a curve, and have no motion to add anyway
+        if let Ok(curve) = UnevenSampleAutoCurve::new(
+            offsets
+                .iter()
+                .map(|(time, offset)| (*time, offset.translation)),
+        ) {
+            additive.add_curve_to_target(
+                *target,
+                AnimatableCurve::new(animated_field!(Transform::translation), curve),
+            );
+        }
+        if let Ok(curve) = UnevenSampleAutoCurve::new(
+            offsets
+                .iter()
+                .map(|(time, offset)| (*time, offset.rotation)),
+        ) {
+            additive.add_curve_to_target(
+                *target,
+                AnimatableCurve::new(animated_field!(Transform::rotation), curve),
+            );
+        }
+        if let Ok(curve) =
+            UnevenSampleAutoCurve::new(offsets.iter().map(|(time, offset)| (*time, offset.scale)))
+        {
+            additive.add_curve_to_target(
+                *target,
+                AnimatableCurve::new(animated_field!(Transform::scale), curve),
+            );
+        }
+    }
+    additive.set_duration(duration);
+    clips.add(additive)
+}
+
+/// Samples the type-erased curves of a clip onto the transform of a bone in a scratch world
+struct PoseSampler {
+    world: World,
+    bone: Entity,
+    query: QueryState<AnimationEntityMut<'static, 'static>>,
+}
+
+impl PoseSampler {
+    fn new() -> Self {
+        let mut world = World::new();
+        let bone = world.spawn(Transform::default()).id();
+        let query = world.query::<AnimationEntityMut>();
+        Self { world, bone, query }
+    }
+
+    /// Returns the local transform of a bone animated by `curves` at `time`
+    fn sample(&mut self, curves: &[VariableCurve], time: f32) -> Transform {
+        self.world
+            .entity_mut(self.bone)
+            .insert(Transform::default());
+        for VariableCurve(curve) in curves {
+            let Ok(bone) = self.query.get_mut(&mut self.world, self.bone) else {
+                continue;
+            };
+            let mut evaluator = curve.create_evaluator();
+            // Curves of anything but the transform, like morph weights, fail to commit and are
+            // left out
+            let _ = curve
+                .apply(&mut *evaluator, time, 1.0, AnimationNodeIndex::new(0))
+                .and_then(|()| evaluator.commit(bone));
+        }
+        self.world
+            .get::<Transform>(self.bone)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Fades layers in and out and plays their clips
+pub fn update_animation_layers(
+    mut character_query: Query<(
+        &mut AnimationLayers,
+        &AnimationNodes,
+        &CharacterAnimationController,
+    )>,
+    mut animation_player_query: Query<&mut AnimationPlayer>,
+    state_machine: Option<Res<AnimationStateMachine>>,
+    time: Res<Time>,
+) {
+    let Some(state_machine) = state_machine else {
+        return;
+    };
+
+    for (mut layers, animation_nodes, animation_controller) in &mut character_query {
+        let Ok(mut animation_player) =
+            animation_player_query.get_mut(animation_controller.animation_player)
+        else {
+            continue;
+        };
+        for (name, state) in &mut layers.layers {
+            let (Some(layer), Some(&node)) = (
+                state_machine.layers.get(name),
+                animation_nodes.layers.get(name),
+            ) else {
+                continue;
+            };
+
+            if state.active && animation_player.animation(node).is_none() {
+                let animation = animation_player.start(node);
+                animation.set_speed(layer.speed);
+                if layer.looping {
+                    animation.repeat();
+                }
+            }
+            // One-shot layers fade out once their clip has played
+            if animation_player
+                .animation(node)
+                .is_some_and(|animation| animation.is_finished())
+            {
+                state.active = false;
+            }
+
+            let target = if state.active { 1.0 } else { 0.0 };
+            state.weight = if layer.blend_duration > 0.0 {
+                let step = time.delta_secs() / layer.blend_duration;
+                state.weight + (target - state.weight).clamp(-step, step)
+            } else {
+                target
+            };
+
+            if !state.active && state.weight <= 0.0 {
+                animation_player.stop(node);
+            } else if let Some(animation) = animation_player.animation_mut(node) {
+                // Additive clips aren't averaged with the pose, they take the weight as-is
+                animation.set_weight(if layer.additive {
+                    state.weight
+                } else {
+                    layer_node_weight(state.weight)
+                });
+            }
+        }
+    }
+}
+
+/// Converts a layer's weight to the weight of its clip node
+///
+/// Bevy averages the poses of all playing clips by weight, and the clips of the state machine add
+/// up to one, so a layer taking a `weight` share of its bones needs `weight / (1 - weight)`.
+fn layer_node_weight(weight: f32) -> f32 {
+    let weight = weight.clamp(0.0, MAX_LAYER_WEIGHT);
+    weight / (1.0 - weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::animation::AnimationTargetId;
+
+    use super::*;
+
+    fn target(name: &'static str) -> AnimationTargetId {
+        AnimationTargetId::from_name(&Name::new(name))
+    }
+
+    fn clip(bone: AnimationTargetId, poses: [(f32, Transform); 2]) -> AnimationClip {
+        let mut clip = AnimationClip::default();
+        clip.add_curve_to_target(
+            bone,
+            AnimatableCurve::new(
+                animated_field!(Transform::translation),
+                UnevenSampleAutoCurve::new(poses.map(|(time, pose)| (time, pose.translation)))
+                    .unwrap(),
+            ),
+        );
+        clip.add_curve_to_target(
+            bone,
+            AnimatableCurve::new(
+                animated_field!(Transform::rotation),
+                UnevenSampleAutoCurve::new(poses.map(|(time, pose)| (time, pose.rotation)))
+                    .unwrap(),
+            ),
+        );
+        clip
+    }
+
+    #[test]
+    fn additive_clip_holds_the_offsets_from_the_reference_pose() {
+        let spine = target("Spine");
+        let head = target("Head");
+        let hunched = Transform::from_xyz(0.0, 0.9, 0.1).with_rotation(Quat::from_rotation_x(0.3));
+        let breathing = hunched
+            .with_translation(Vec3::new(0.0, 1.0, 0.1))
+            .with_rotation(Quat::from_rotation_x(0.5));
+        let upright = Transform::from_xyz(0.0, 1.0, 0.0);
+
+        let mut clips = Assets::<AnimationClip>::default();
+        let mut tired = clip(spine, [(0.0, hunched), (1.0, breathing)]);
+        for (bone, curves) in clip(head, [(0.0, upright), (1.0, hunched)]).curves() {
+            tired.curves_mut().insert(*bone, curves.clone());
+        }
+        let tired = clips.add(tired);
+        let idle = clips.add(clip(spine, [(0.0, upright), (1.0, upright)]));
+
+        let additive = additive_clip(&mut clips, &tired, Some(&idle));
+        let additive = clips.get(&additive).unwrap();
+        let mut sampler = PoseSampler::new();
+
+        // The spine is measured against the reference clip
+        let start = sampler.sample(additive.curves_for_target(spine).unwrap(), 0.0);
+        assert!(
+            start
+                .translation
+                .abs_diff_eq(Vec3::new(0.0, -0.1, 0.1), 1e-5)
+        );
+        assert!(start.rotation.abs_diff_eq(Quat::from_rotation_x(0.3), 1e-5));
+        let end = sampler.sample(additive.curves_for_target(spine).unwrap(), 1.0);
+        assert!(end.translation.abs_diff_eq(Vec3::new(0.0, 0.0, 0.1), 1e-5));
+        assert!(end.rotation.abs_diff_eq(Quat::from_rotation_x(0.5), 1e-5));
+
+        // The reference doesn't animate the head, so only its own motion is added
+        let start = sampler.sample(additive.curves_for_target(head).unwrap(), 0.0);
+        assert!(start.translation.abs_diff_eq(Vec3::ZERO, 1e-5));
+        assert!(start.rotation.abs_diff_eq(Quat::IDENTITY, 1e-5));
+        let end = sampler.sample(additive.curves_for_target(head).unwrap(), 1.0);
+        assert!(end.translation.abs_diff_eq(Vec3::new(0.0, -0.1, 0.1), 1e-5));
+        assert!(end.rotation.abs_diff_eq(Quat::from_rotation_x(0.3), 1e-5));
+    }
+}
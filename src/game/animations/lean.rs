@@ -0,0 +1,175 @@
+//! Procedural lean of the body into turns and against acceleration.
+//!
+//! The lean angles follow their targets on a spring in the physics step, where the velocities
+//! change, and are added on top of the animated pose every frame.
+
+use avian3d::prelude::*;
+use bevy::{app::AnimationSystems, prelude::*, transform::TransformSystems};
+
+use crate::{game::configs::AnimationBlendingConfig, screens::Screen};
+
+use super::{
+    foot_ik::place_feet,
+    inertialization::apply_inertialization,
+    skeleton::{Bones, find_bone, parent_world_transform},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (resolve_lean_rigs, update_lean)
+            .chain()
+            .run_if(in_state(Screen::Gameplay)),
+    );
+    app.add_systems(
+        PostUpdate,
+        apply_lean
+            .after(AnimationSystems)
+            .after(apply_inertialization)
+            .before(place_feet)
+            .before(TransformSystems::Propagate),
+    );
+}
+
+/// Leans the character into turns and against acceleration, see
+/// [`LeanConfig`](crate::game::configs::LeanConfig)
+///
+/// Add to the character root, next to its [`SceneRoot`] child.
+#[derive(Component, Debug, Clone)]
+pub struct ProceduralLean {
+    pub enabled: bool,
+}
+
+impl Default for ProceduralLean {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Lean bones of a [`ProceduralLean`] character and the spring state
+#[derive(Component, Debug)]
+pub(super) struct LeanRig {
+    bones: Vec<(Entity, f32)>,
+    /// Current sideways (`x`) and forward (`y`) lean in radians
+    angles: Vec2,
+    angular_velocity: Vec2,
+    previous_velocity: Vec3,
+    /// World-space rotation of the full lean
+    rotation: Quat,
+}
+
+/// Finds the lean bones of characters whose scene has spawned, and again after the config
+/// is reloaded
+fn resolve_lean_rigs(
+    mut commands: Commands,
+    characters: Query<(Entity, &Children, Has<LeanRig>), With<ProceduralLean>>,
+    models: Query<Entity, With<SceneRoot>>,
+    descendants: Query<&Children>,
+    names: Query<&Name>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
+) {
+    let Some(blend_config) = blend_config else {
+        return;
+    };
+    for (character, children, has_rig) in &characters {
+        if has_rig && !blend_config.is_changed() {
+            continue;
+        }
+        let Some(model) = children.iter().find(|child| models.contains(*child)) else {
+            continue;
+        };
+        let bones: Vec<_> = blend_config
+            .lean
+            .bones
+            .iter()
+            .filter_map(|lean_bone| {
+                let bone = find_bone(model, &lean_bone.bone, &descendants, &names)?;
+                Some((bone, lean_bone.weight))
+            })
+            .collect();
+        // The scene may not have spawned yet
+        if bones.is_empty() {
+            continue;
+        }
+        commands.entity(character).insert(LeanRig {
+            bones,
+            angles: Vec2::ZERO,
+            angular_velocity: Vec2::ZERO,
+            previous_velocity: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+        });
+    }
+}
+
+/// Moves the lean angles toward the lean caused by the character's turning and acceleration
+fn update_lean(
+    mut characters: Query<(
+        &ProceduralLean,
+        &mut LeanRig,
+        &Transform,
+        &LinearVelocity,
+        &AngularVelocity,
+    )>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
+    time: Res<Time>,
+) {
+    let Some(blend_config) = blend_config else {
+        return;
+    };
+    let config = &blend_config.lean;
+    let delta = time.delta_secs();
+    if delta <= 0.0 {
+        return;
+    }
+
+    for (lean, mut rig, transform, linear_velocity, angular_velocity) in &mut characters {
+        let velocity = Vec3::new(linear_velocity.x, 0.0, linear_velocity.z);
+        let acceleration = (velocity - rig.previous_velocity) / delta;
+        rig.previous_velocity = velocity;
+
+        let target = if lean.enabled {
+            // Turning left (positive yaw rate) leans left, like the centripetal acceleration
+            let turn = -angular_velocity.y * velocity.length() * config.turn_lean;
+            let side = turn + acceleration.dot(*transform.right()) * config.acceleration_lean;
+            let forward = acceleration.dot(*transform.forward()) * config.acceleration_lean;
+            Vec2::new(
+                side.clamp(-config.max_side_angle, config.max_side_angle)
+                    .to_radians(),
+                forward
+                    .clamp(-config.max_forward_angle, config.max_forward_angle)
+                    .to_radians(),
+            )
+        } else {
+            Vec2::ZERO
+        };
+
+        let spring =
+            (target - rig.angles) * config.stiffness - rig.angular_velocity * config.damping;
+        rig.angular_velocity += spring * delta;
+        let step = rig.angular_velocity * delta;
+        rig.angles += step;
+
+        // Rotating around the forward axis tilts right, around the right axis tilts back
+        rig.rotation = Quat::from_axis_angle(*transform.forward(), rig.angles.x)
+            * Quat::from_axis_angle(*transform.right(), -rig.angles.y);
+    }
+}
+
+/// Tilts the lean bones in world space, each by its share of the lean
+pub(super) fn apply_lean(characters: Query<&LeanRig>, mut bones: Bones) {
+    for rig in &characters {
+        if rig.angles.abs().max_element() < 1e-4 {
+            continue;
+        }
+        for &(bone, weight) in &rig.bones {
+            let parent_rotation = parent_world_transform(bone, &bones).rotation;
+            let Ok((mut transform, _)) = bones.get_mut(bone) else {
+                continue;
+            };
+            let lean = Quat::IDENTITY.slerp(rig.rotation, weight);
+            transform.rotation =
+                (parent_rotation.inverse() * lean * parent_rotation * transform.rotation)
+                    .normalize();
+        }
+    }
+}
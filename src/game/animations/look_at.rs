@@ -0,0 +1,211 @@
+//! Procedural look-at turning the head and upper spine toward a point in the world.
+//!
+//! By default characters look where the camera looks: a ray along the camera's forward axis finds
+//! the aim point. The turn is clamped to the configured limits, eased toward moving targets, and
+//! faded out while the target is out of reach, e.g. behind the character.
+
+use std::f32::consts::FRAC_PI_6;
+
+use avian3d::prelude::*;
+use bevy::{app::AnimationSystems, prelude::*, transform::TransformSystems};
+
+use crate::{
+    game::{configs::AnimationBlendingConfig, third_person_camera::ThirdPersonCamera},
+    screens::Screen,
+};
+
+use super::{
+    foot_ik::place_feet,
+    lean::apply_lean,
+    skeleton::{Bones, find_bone, parent_world_transform, world_transform},
+};
+
+/// How far past the yaw limit the target may be before the look-at fades out
+const FADE_OUT_MARGIN: f32 = FRAC_PI_6;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (resolve_look_at_rigs, aim_look_at)
+            .chain()
+            .run_if(in_state(Screen::Gameplay)),
+    );
+    // On top of the lean, so the head still finds the target while the body tilts
+    app.add_systems(
+        PostUpdate,
+        apply_look_at
+            .after(AnimationSystems)
+            .after(apply_lean)
+            .before(place_feet)
+            .before(TransformSystems::Propagate),
+    );
+}
+
+/// Turns the character's head and upper spine toward a target, see
+/// [`LookAtConfig`](crate::game::configs::LookAtConfig)
+///
+/// Add to the character root, next to its [`SceneRoot`] child.
+#[derive(Component, Debug, Clone)]
+pub struct LookAt {
+    pub enabled: bool,
+    /// World-space point to look at, the camera's aim point if `None`
+    pub target: Option<Vec3>,
+}
+
+impl Default for LookAt {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target: None,
+        }
+    }
+}
+
+/// Look-at bones of a [`LookAt`] character and the blending state
+#[derive(Component, Debug)]
+struct LookAtRig {
+    /// Bones with their share of the turn, the last one is taken as the eyes
+    bones: Vec<(Entity, f32)>,
+    aim_point: Option<Vec3>,
+    /// Current yaw (`x`, positive to the left) and pitch (`y`, positive up) in radians
+    angles: Vec2,
+    weight: f32,
+}
+
+/// Finds the look-at bones of characters whose scene has spawned, and again after the config
+/// is reloaded
+fn resolve_look_at_rigs(
+    mut commands: Commands,
+    characters: Query<(Entity, &Children, Has<LookAtRig>), With<LookAt>>,
+    models: Query<Entity, With<SceneRoot>>,
+    descendants: Query<&Children>,
+    names: Query<&Name>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
+) {
+    let Some(blend_config) = blend_config else {
+        return;
+    };
+    for (character, children, has_rig) in &characters {
+        if has_rig && !blend_config.is_changed() {
+            continue;
+        }
+        let Some(model) = children.iter().find(|child| models.contains(*child)) else {
+            continue;
+        };
+        let bones: Vec<_> = blend_config
+            .look_at
+            .bones
+            .iter()
+            .filter_map(|look_bone| {
+                let bone = find_bone(model, &look_bone.bone, &descendants, &names)?;
+                Some((bone, look_bone.weight))
+            })
+            .collect();
+        // The scene may not have spawned yet
+        if bones.is_empty() {
+            continue;
+        }
+        commands.entity(character).insert(LookAtRig {
+            bones,
+            aim_point: None,
+            angles: Vec2::ZERO,
+            weight: 0.0,
+        });
+    }
+}
+
+/// Picks the point every character looks at, casting the camera's forward ray for characters
+/// without a target of their own
+fn aim_look_at(
+    mut characters: Query<(Entity, &LookAt, &mut LookAtRig)>,
+    camera: Query<&GlobalTransform, With<ThirdPersonCamera>>,
+    spatial_query: SpatialQuery,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
+) {
+    let Some(blend_config) = blend_config else {
+        return;
+    };
+    let max_distance = blend_config.look_at.max_distance;
+    let camera = camera.single().ok();
+
+    for (character, look_at, mut rig) in &mut characters {
+        rig.aim_point = look_at.target.or_else(|| {
+            let camera = camera?;
+            let origin = camera.translation();
+            let direction = camera.forward();
+            let filter = SpatialQueryFilter::from_excluded_entities([character]);
+            let distance = spatial_query
+                .cast_ray(origin, direction, max_distance, true, &filter)
+                .map_or(max_distance, |hit| hit.distance);
+            Some(origin + direction * distance)
+        });
+    }
+}
+
+/// Eases the look-at toward its target and turns the look-at bones in world space, each by its
+/// share of the turn
+fn apply_look_at(
+    mut characters: Query<(Entity, &LookAt, &mut LookAtRig)>,
+    mut bones: Bones,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
+    time: Res<Time>,
+) {
+    let Some(blend_config) = blend_config else {
+        return;
+    };
+    let config = &blend_config.look_at;
+    let delta = time.delta_secs();
+    let max_yaw = config.max_yaw.to_radians();
+    let max_pitch = config.max_pitch.to_radians();
+
+    for (character, look_at, mut rig) in &mut characters {
+        let Some(&(eyes, _)) = rig.bones.last() else {
+            continue;
+        };
+        let body = world_transform(character, &bones);
+
+        // Direction to the target relative to the character, which faces -Z
+        let target = rig
+            .aim_point
+            .filter(|_| look_at.enabled)
+            .and_then(|aim_point| {
+                let eyes = world_transform(eyes, &bones).translation;
+                let local = body.rotation.inverse() * (aim_point - eyes);
+                let yaw = f32::atan2(-local.x, -local.z);
+                let pitch = f32::atan2(local.y, Vec2::new(local.x, local.z).length());
+                (yaw.abs() <= max_yaw + FADE_OUT_MARGIN).then_some(Vec2::new(yaw, pitch))
+            });
+
+        // Fading out keeps the last angles, so the head turns back without snapping
+        let target_weight = if let Some(target) = target {
+            let clamped = Vec2::new(
+                target.x.clamp(-max_yaw, max_yaw),
+                target.y.clamp(-max_pitch, max_pitch),
+            );
+            let follow = 1.0 - (-config.follow_speed * delta).exp();
+            rig.angles = rig.angles.lerp(clamped, follow);
+            1.0
+        } else {
+            0.0
+        };
+        let step = config.blend_speed * delta;
+        rig.weight += (target_weight - rig.weight).clamp(-step, step);
+        if rig.weight < 1e-4 {
+            continue;
+        }
+
+        let angles = rig.angles * rig.weight;
+        let turn = Quat::from_axis_angle(Vec3::Y, angles.x)
+            * Quat::from_axis_angle(body.rotation * Vec3::X, angles.y);
+        for &(bone, weight) in &rig.bones {
+            let parent_rotation = parent_world_transform(bone, &bones).rotation;
+            let Ok((mut transform, _)) = bones.get_mut(bone) else {
+                continue;
+            };
+            let share = Quat::IDENTITY.slerp(turn, weight);
+            transform.rotation =
+                (parent_rotation.inverse() * share * parent_rotation * transform.rotation)
+                    .normalize();
+        }
+    }
+}
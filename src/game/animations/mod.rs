@@ -1,25 +1,33 @@
-mod blending;
-mod animation_controller;
+pub mod animation_controller;
+mod blend_space;
 mod controls;
+pub mod events;
+pub mod foot_ik;
+mod footsteps;
+pub mod inertialization;
+pub mod layers;
+pub mod lean;
+pub mod look_at;
 pub mod models;
+pub mod motion_warping;
+pub mod retargeting;
+pub mod root_motion;
+mod skeleton;
+mod sync_groups;
+pub mod transitions;
 
 use bevy::prelude::*;
-use bevy_tnua::prelude::*;
+use bevy_tnua::{control_helpers::TnuaCrouchEnforcerPlugin, prelude::*};
 use bevy_tnua_avian3d::*;
 
 use crate::screens::Screen;
 
 use self::{
-    // blending::{
-    //     setup_animation_graph,
-    //     update_animation_state,
-    //     PreviousAnimationState,
-    // },
     animation_controller::{
-        setup_animation_graph,
-        update_animation_state,
+        rebuild_animation_graphs, setup_animation_graph, update_animation_state,
     },
     controls::apply_controls,
+    layers::update_animation_layers,
 };
 
 pub(super) fn plugin(app: &mut App) {
@@ -27,26 +35,38 @@ pub(super) fn plugin(app: &mut App) {
     app.add_plugins((
         TnuaControllerPlugin::new(FixedUpdate),
         TnuaAvian3dPlugin::new(FixedUpdate),
+        TnuaCrouchEnforcerPlugin::new(FixedUpdate),
     ));
 
-    // Initialize animation state tracking
-    // app.init_resource::<PreviousAnimationState>();
+    app.add_plugins((
+        footsteps::plugin,
+        foot_ik::plugin,
+        inertialization::plugin,
+        lean::plugin,
+        look_at::plugin,
+        motion_warping::plugin,
+        retargeting::plugin,
+        root_motion::plugin,
+    ));
 
     // Animation systems - multi-stage loading:
     // 1. PlayerGltfAsset is loaded (handled in player module)
     // 2. PlayerAssets is extracted from GLTF (handled in player module)
-    // 3. Build animation graph from PlayerAssets -> AnimationNodes resource
-    // 4. Apply animations to player
+    // 3. Build an animation graph from PlayerAssets and the AnimationStateMachine for every
+    //    character's AnimationPlayer -> AnimationNodes on the character
+    // 4. Evaluate the state machine of every character every frame
     app.add_systems(
         FixedUpdate,
         (
-            // Setup animation graph once PlayerAssets is available
+            // Rebuild the graphs when their configs are hot-reloaded
+            rebuild_animation_graphs.before(setup_animation_graph),
+            // Setup animation graphs once PlayerAssets is available
             setup_animation_graph,
             // Attach and update animations
             update_animation_state,
-
+            update_animation_layers.after(update_animation_state),
             apply_controls.in_set(TnuaUserControlsSystems),
-        ).run_if(in_state(Screen::Gameplay)),
+        )
+            .run_if(in_state(Screen::Gameplay)),
     );
 }
-
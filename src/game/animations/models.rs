@@ -1,19 +1,53 @@
 use bevy::prelude::*;
 
-/// Current animation state of the player
-#[derive(Component, Debug, Clone, Copy, PartialEq)]
-pub enum AnimationState {
-    Idle,
-    Walking,
-    Running(f32),  // Speed value - used for walk-run blending
-    Moving(f32),  // Speed value - used for walk-run blending
-    Jumping,
+use crate::game::configs::JumpPhase;
+
+/// Current animation state of a character, named after a state of the
+/// [`AnimationStateMachine`](crate::game::configs::AnimationStateMachine)
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct AnimationState {
+    /// Name of the active state, empty until the initial state has been entered
+    pub current: String,
+    /// Downward speed of the character in its last airborne frame, i.e. its landing impact
+    pub impact_speed: f32,
+    /// Turn performed by the current state, for states with `sync_turn`
+    pub turn: Option<StateTurn>,
+}
+
+/// A turn that follows the progress of the state's clip
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StateTurn {
+    /// Facing when the state was entered
+    pub from: Dir3,
+    /// Angle in radians to turn by over the clip, positive to the left
+    pub angle: f32,
 }
 
-impl Default for AnimationState {
-    fn default() -> Self {
-        Self::Idle
-    }
+/// Snapshot of the character's movement that state machine transitions are checked against
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnimationParameters {
+    /// Horizontal speed of the character
+    pub speed: f32,
+    /// Horizontal velocity relative to the character's facing, `x` right and `y` forward
+    pub local_velocity: Vec2,
+    /// Angle in degrees from the facing to the input direction, positive to the left
+    pub turn_angle: f32,
+    /// Vertical speed of the character, positive is up
+    pub vertical_speed: f32,
+    /// Whether the character is standing on the ground
+    pub grounded: bool,
+    /// Phase of the active jump action, if any
+    pub jump_phase: Option<JumpPhase>,
+    /// Downward speed when the character last touched the ground
+    pub impact_speed: f32,
+    /// Name of the active Tnua action, if any
+    pub action: Option<&'static str>,
+    /// Whether the player is aiming with the camera
+    pub aiming: bool,
+    /// Whether a crouch action is active and not standing back up
+    pub crouching: bool,
+    /// Whether the clip of the current state has played to the end
+    pub finished: bool,
 }
 
 /// Component that stores the animation graph and player for a character
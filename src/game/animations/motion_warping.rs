@@ -0,0 +1,210 @@
+//! Motion warping, carrying a character along a path bent to meet points in the world at set
+//! times of its clip.
+//!
+//! A warped move takes the character off Tnua and the physics: the body is kinematic and Tnua
+//! only senses while the move lasts, so neither the floating basis nor obstacles on the way push
+//! the character off the path. The root bone's translation is kept out of the pose, see
+//! [`RootMotion::vertical`], so the model stays on the path too.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::{TnuaToggle, prelude::*};
+
+use crate::screens::Screen;
+
+use super::root_motion::RootMotion;
+
+/// How fast a warped character turns to face along its path
+const TURN_SPEED: f32 = 15.0;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        follow_motion_warp
+            .after(TnuaUserControlsSystems)
+            .run_if(in_state(Screen::Gameplay)),
+    );
+}
+
+/// Carries a character along a warped path, see [`MotionWarp::start`]
+#[derive(Component, Debug, Default)]
+pub struct MotionWarp {
+    path: Option<WarpPath>,
+}
+
+/// Path of a warped move and the progress along it
+#[derive(Debug)]
+struct WarpPath {
+    /// Seconds since the start at which the path passes each of its points
+    times: Vec<f32>,
+    curve: CubicCurve<Vec3>,
+    elapsed: f32,
+    /// Velocity the character is left with at the end
+    exit_velocity: Vec3,
+    /// Whether the character has been taken off Tnua and the physics yet
+    taken: bool,
+}
+
+impl WarpPath {
+    /// Position on the path at `time` seconds since the start
+    fn position(&self, time: f32) -> Vec3 {
+        let segment = self
+            .times
+            .windows(2)
+            .position(|window| time < window[1])
+            .unwrap_or(self.times.len() - 2);
+        let (from, to) = (self.times[segment], self.times[segment + 1]);
+        let progress = ((time - from) / (to - from)).clamp(0.0, 1.0);
+        self.curve.position(segment as f32 + progress)
+    }
+
+    fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or_default()
+    }
+}
+
+impl MotionWarp {
+    /// Starts carrying the character's center through `points`, each a time in seconds since the
+    /// start and a world-space position, on a smooth path through all of them
+    ///
+    /// The first point should be where the character is now. Points must be in order of time.
+    /// Returns whether the move started, the character is left as it is otherwise.
+    pub fn start(
+        &mut self,
+        points: impl IntoIterator<Item = (f32, Vec3)>,
+        exit_velocity: Vec3,
+    ) -> bool {
+        let (times, positions): (Vec<_>, Vec<_>) = points.into_iter().unzip();
+        if times.windows(2).any(|window| window[1] <= window[0]) {
+            warn!("Motion warp points out of order: {times:?}");
+            return false;
+        }
+        let Ok(curve) = CubicCardinalSpline::new_catmull_rom(positions).to_curve() else {
+            warn!("Motion warp needs at least two points");
+            return false;
+        };
+        self.path = Some(WarpPath {
+            times,
+            curve,
+            elapsed: 0.0,
+            exit_velocity,
+            taken: false,
+        });
+        true
+    }
+
+    /// Whether a warped move is carrying the character
+    pub fn is_active(&self) -> bool {
+        self.path.is_some()
+    }
+}
+
+/// Moves warped characters along their paths, and hands them back to Tnua once they arrive
+fn follow_motion_warp(
+    mut commands: Commands,
+    mut characters: Query<(
+        Entity,
+        &mut MotionWarp,
+        &Position,
+        &mut Rotation,
+        &mut LinearVelocity,
+        Option<&mut RootMotion>,
+    )>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut motion_warp, position, mut rotation, mut velocity, root_motion) in
+        &mut characters
+    {
+        let active = motion_warp.is_active();
+        if let Some(mut root_motion) = root_motion {
+            root_motion.vertical = active;
+        }
+        let Some(path) = motion_warp.path.as_mut() else {
+            continue;
+        };
+        if delta <= 0.0 {
+            continue;
+        }
+
+        if !path.taken {
+            commands
+                .entity(entity)
+                .insert((RigidBody::Kinematic, TnuaToggle::SenseOnly));
+            path.taken = true;
+        }
+
+        path.elapsed += delta;
+        if path.elapsed >= path.duration() {
+            commands
+                .entity(entity)
+                .insert((RigidBody::Dynamic, TnuaToggle::Enabled));
+            velocity.0 = path.exit_velocity;
+            motion_warp.path = None;
+            continue;
+        }
+
+        // Reach the point on the path by the end of this step
+        let target = path.position(path.elapsed);
+        velocity.0 = (target - position.0) / delta;
+
+        if let Ok(forward) = Dir3::new(velocity.0.reject_from(Vec3::Y)) {
+            let facing = Transform::default().looking_to(forward, Vec3::Y).rotation;
+            rotation.0 = rotation.0.slerp(facing, (TURN_SPEED * delta).min(1.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [(f32, Vec3); 3] = [
+        (0.0, Vec3::ZERO),
+        (0.5, Vec3::new(1.0, 1.0, 0.0)),
+        (1.5, Vec3::new(3.0, 1.0, 0.0)),
+    ];
+
+    fn started(points: &[(f32, Vec3)]) -> WarpPath {
+        let mut warp = MotionWarp::default();
+        assert!(warp.start(points.iter().copied(), Vec3::ZERO));
+        warp.path.unwrap()
+    }
+
+    #[test]
+    fn passes_each_point_at_its_time() {
+        let path = started(&POINTS);
+        for (time, point) in POINTS {
+            assert!(path.position(time).abs_diff_eq(point, 1e-4));
+        }
+        assert_eq!(path.duration(), 1.5);
+    }
+
+    #[test]
+    fn clamps_outside_the_path() {
+        let path = started(&POINTS);
+        assert!(path.position(-1.0).abs_diff_eq(Vec3::ZERO, 1e-4));
+        assert!(path.position(3.0).abs_diff_eq(POINTS[2].1, 1e-4));
+    }
+
+    #[test]
+    fn segments_are_timed_by_their_points() {
+        // Evenly spaced points on a line, but the second segment takes three times as long
+        let path = started(&[
+            (0.0, Vec3::ZERO),
+            (1.0, Vec3::X),
+            (4.0, Vec3::X * 2.0),
+            (5.0, Vec3::X * 3.0),
+        ]);
+        assert!(path.position(0.5).abs_diff_eq(Vec3::X * 0.5, 1e-4));
+        assert!(path.position(2.5).abs_diff_eq(Vec3::X * 1.5, 1e-4));
+    }
+
+    #[test]
+    fn rejects_invalid_points() {
+        let mut warp = MotionWarp::default();
+        assert!(!warp.start([(0.0, Vec3::ZERO)], Vec3::ZERO));
+        assert!(!warp.start([(0.5, Vec3::ZERO), (0.5, Vec3::X)], Vec3::ZERO));
+        assert!(!warp.is_active());
+    }
+}
@@ -0,0 +1,377 @@
+//! Retargeting of the player's animation clips onto characters with a different rig.
+//!
+//! A [`BoneMap`] names the character bone for every bone of the clip rig. When the character's
+//! scene spawns, its mapped bones become [`AnimationTarget`]s of the character's own rig, and the
+//! animation graph of the character plays copies of the clips keyed to those targets. Rigs rarely
+//! share a rest pose, so after the clips are sampled every bone takes the clip's rotation relative
+//! to the clip rig's rest pose on top of its own rest pose, and the root translation is scaled to
+//! the character's height.
+
+use std::collections::HashMap;
+
+use bevy::{
+    animation::{AnimationCurves, AnimationTarget, AnimationTargetId},
+    app::AnimationSystems,
+    gltf::{Gltf, GltfNode},
+    prelude::*,
+};
+
+use crate::game::{configs::BoneMap, player::PlayerGltfAsset};
+
+use super::{root_motion::extract_root_motion, skeleton::find_bone};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(Update, register_retargeted_rigs);
+    app.add_systems(
+        PostUpdate,
+        retarget_pose
+            .after(AnimationSystems)
+            .before(extract_root_motion),
+    );
+}
+
+/// Plays the player's animation clips on a character whose rig differs from the player's
+///
+/// Add to the character root, next to its [`SceneRoot`] child. The mapped bones are expected to
+/// be animated every frame, as the rest pose compensation is applied on top of the sampled pose.
+#[derive(Component, Debug, Clone)]
+pub struct Retargeting {
+    pub bone_map: Handle<BoneMap>,
+}
+
+/// Mapped bones of a [`Retargeting`] character, added once its scene has spawned
+///
+/// The animation graph of a retargeted character is only built once this is present.
+#[derive(Component, Debug)]
+pub struct RetargetRig {
+    bones: Vec<RetargetBone>,
+    /// Character bone target for every mapped target of the clip rig
+    targets: HashMap<AnimationTargetId, AnimationTargetId>,
+    /// Character height relative to the clip rig, measured at the root bone
+    height_scale: f32,
+}
+
+#[derive(Debug)]
+struct RetargetBone {
+    entity: Entity,
+    source_rest: Transform,
+    target_rest: Transform,
+    root: bool,
+}
+
+/// Animation target id and rest pose of a bone of the clip rig
+#[derive(Debug, Clone, Copy)]
+struct SourceBone {
+    id: AnimationTargetId,
+    rest: Transform,
+}
+
+impl RetargetRig {
+    /// Returns a copy of `clip` that animates the character's bones instead of the clip rig's
+    pub fn retarget_clip(&self, clip: &AnimationClip) -> AnimationClip {
+        retarget_clip(clip, &self.targets)
+    }
+}
+
+/// Copies `clip` with the curves of every mapped target moved to its counterpart in `targets`
+///
+/// Curves of unmapped targets are dropped, as the character has no bone to play them on. The
+/// duration and events of the clip are kept.
+fn retarget_clip(
+    clip: &AnimationClip,
+    targets: &HashMap<AnimationTargetId, AnimationTargetId>,
+) -> AnimationClip {
+    let mut retargeted = clip.clone();
+    *retargeted.curves_mut() = clip
+        .curves()
+        .iter()
+        .filter_map(|(source, curves)| Some((*targets.get(source)?, curves.clone())))
+        .collect::<AnimationCurves>();
+    retargeted
+}
+
+/// Builds the target id of a bone of the character rig
+///
+/// Like the GLTF loader, the id is made of the names from the animation root down to the bone,
+/// so clips of the character's own GLTF keep playing next to the retargeted ones.
+fn target_id(
+    animation_player: Entity,
+    bone: Entity,
+    names: &Query<&Name>,
+    parents: &Query<&ChildOf>,
+) -> AnimationTargetId {
+    let mut path: Vec<&Name> = std::iter::once(bone)
+        .chain(
+            parents
+                .iter_ancestors(bone)
+                .take_while(|ancestor| *ancestor != animation_player),
+        )
+        .chain(std::iter::once(animation_player))
+        .filter_map(|entity| names.get(entity).ok())
+        .collect();
+    path.reverse();
+    AnimationTargetId::from_names(path.into_iter())
+}
+
+/// Collects the bones of the clip rig from the player GLTF, keyed by name
+///
+/// The ids are built from the node path below the scene root, like the GLTF loader does for the
+/// targets of the clips.
+fn source_bones(gltf: &Gltf, gltf_nodes: &Assets<GltfNode>) -> HashMap<String, SourceBone> {
+    let mut parents = HashMap::new();
+    for node in gltf.nodes.iter().filter_map(|node| gltf_nodes.get(node)) {
+        for child in node
+            .children
+            .iter()
+            .filter_map(|child| gltf_nodes.get(child))
+        {
+            parents.insert(child.index, node.index);
+        }
+    }
+    let nodes: HashMap<usize, &GltfNode> = gltf
+        .nodes
+        .iter()
+        .filter_map(|node| gltf_nodes.get(node))
+        .map(|node| (node.index, node))
+        .collect();
+
+    nodes
+        .values()
+        .map(|node| {
+            let mut path = vec![Name::new(node.name.clone())];
+            let mut index = node.index;
+            while let Some(parent) = parents.get(&index) {
+                path.push(Name::new(nodes[parent].name.clone()));
+                index = *parent;
+            }
+            path.reverse();
+            let bone = SourceBone {
+                id: AnimationTargetId::from_names(path.iter()),
+                rest: node.transform,
+            };
+            (node.name.clone(), bone)
+        })
+        .collect()
+}
+
+/// Makes the mapped bones of newly spawned [`Retargeting`] characters animation targets of the
+/// character's own rig
+fn register_retargeted_rigs(
+    mut commands: Commands,
+    characters: Query<(Entity, &Retargeting, &Children), Without<RetargetRig>>,
+    models: Query<Entity, With<SceneRoot>>,
+    descendants: Query<&Children>,
+    names: Query<&Name>,
+    parents: Query<&ChildOf>,
+    transforms: Query<&Transform>,
+    animation_players: Query<(), With<AnimationPlayer>>,
+    bone_maps: Res<Assets<BoneMap>>,
+    player_gltf: Option<Res<PlayerGltfAsset>>,
+    gltfs: Res<Assets<Gltf>>,
+    gltf_nodes: Res<Assets<GltfNode>>,
+) {
+    if characters.is_empty() {
+        return;
+    }
+    let Some(gltf) = player_gltf.and_then(|player_gltf| gltfs.get(&player_gltf.gltf)) else {
+        return;
+    };
+    let source = source_bones(gltf, &gltf_nodes);
+
+    for (character, retargeting, children) in &characters {
+        let Some(bone_map) = bone_maps.get(&retargeting.bone_map) else {
+            continue;
+        };
+        let Some(model) = children.iter().find(|child| models.contains(*child)) else {
+            continue;
+        };
+        // The scene has not spawned yet
+        if !descendants.contains(model) {
+            continue;
+        }
+
+        // Characters without clips of their own have no player yet
+        let animation_player = descendants
+            .iter_descendants(model)
+            .find(|entity| animation_players.contains(*entity))
+            .unwrap_or(model);
+        if !animation_players.contains(animation_player) {
+            commands
+                .entity(animation_player)
+                .insert(AnimationPlayer::default());
+        }
+
+        let mut bones = Vec::new();
+        let mut targets = HashMap::new();
+        let mut height_scale = 1.0;
+        for (source_name, target_name) in &bone_map.bones {
+            let Some(source_bone) = source.get(source_name) else {
+                warn!("Bone `{source_name}` of the bone map is not in the player rig");
+                continue;
+            };
+            let Some(entity) = find_bone(model, target_name, &descendants, &names) else {
+                warn!("Bone `{target_name}` of the bone map is not in the character rig");
+                continue;
+            };
+            let Ok(&target_rest) = transforms.get(entity) else {
+                continue;
+            };
+
+            let root = *source_name == bone_map.root;
+            if root && source_bone.rest.translation.y.abs() > f32::EPSILON {
+                // Heights in each rig's own units, which also covers rigs in centimeters
+                height_scale = target_rest.translation.y / source_bone.rest.translation.y;
+            }
+            let id = target_id(animation_player, entity, &names, &parents);
+            commands.entity(entity).insert(AnimationTarget {
+                id,
+                player: animation_player,
+            });
+            targets.insert(source_bone.id, id);
+            bones.push(RetargetBone {
+                entity,
+                source_rest: source_bone.rest,
+                target_rest,
+                root,
+            });
+        }
+
+        info!(
+            "Retargeted {} bones onto {character}, height scale {height_scale}",
+            bones.len()
+        );
+        commands.entity(character).insert(RetargetRig {
+            bones,
+            targets,
+            height_scale,
+        });
+    }
+}
+
+/// Moves the sampled clip rig pose onto the rest pose of the character
+fn retarget_pose(rigs: Query<&RetargetRig>, mut transforms: Query<&mut Transform>) {
+    for rig in &rigs {
+        for bone in &rig.bones {
+            let Ok(mut transform) = transforms.get_mut(bone.entity) else {
+                continue;
+            };
+            transform.rotation = (bone.target_rest.rotation
+                * bone.source_rest.rotation.inverse()
+                * transform.rotation)
+                .normalize();
+            transform.translation = if bone.root {
+                bone.target_rest.translation
+                    + (transform.translation - bone.source_rest.translation) * rig.height_scale
+            } else {
+                bone.target_rest.translation
+            };
+            transform.scale = bone.target_rest.scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        animation::animated_field, ecs::system::RunSystemOnce, math::curve::UnevenSampleAutoCurve,
+    };
+
+    use super::*;
+
+    fn target(path: &[&'static str]) -> AnimationTargetId {
+        AnimationTargetId::from_names(
+            path.iter()
+                .map(|name| Name::new(*name))
+                .collect::<Vec<_>>()
+                .iter(),
+        )
+    }
+
+    fn rotation_curve() -> impl AnimationCurve {
+        AnimatableCurve::new(
+            animated_field!(Transform::rotation),
+            UnevenSampleAutoCurve::new([(0.0, Quat::IDENTITY), (1.5, Quat::from_rotation_y(1.0))])
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn retarget_clip_moves_curves_to_the_character_rig() {
+        let source_hips = target(&["Armature", "mixamorig:Hips"]);
+        let source_tail = target(&["Armature", "mixamorig:Hips", "mixamorig:Tail"]);
+        let character_hips = target(&["Root", "Hips"]);
+
+        let mut clip = AnimationClip::default();
+        clip.add_curve_to_target(source_hips, rotation_curve());
+        clip.add_curve_to_target(source_tail, rotation_curve());
+        let targets = HashMap::from([(source_hips, character_hips)]);
+
+        let retargeted = retarget_clip(&clip, &targets);
+
+        assert_eq!(retargeted.curves().len(), 1);
+        assert_eq!(
+            retargeted.curves_for_target(character_hips).map(Vec::len),
+            Some(1)
+        );
+        assert!(retargeted.curves_for_target(source_hips).is_none());
+        assert_eq!(retargeted.duration(), clip.duration());
+    }
+
+    #[test]
+    fn retarget_pose_moves_the_clip_pose_onto_the_character_rest_pose() {
+        let mut world = World::new();
+        // Sampled clip pose: the hips raised and moved forward, the spine bent from its rest
+        let hips = world
+            .spawn(Transform {
+                translation: Vec3::new(0.0, 1.2, 0.5),
+                rotation: Quat::from_rotation_y(1.0),
+                ..default()
+            })
+            .id();
+        let spine = world
+            .spawn(Transform {
+                translation: Vec3::new(0.0, 0.5, 0.0),
+                rotation: Quat::from_rotation_z(0.3) * Quat::from_rotation_x(0.2),
+                ..default()
+            })
+            .id();
+        // The character is twice as tall, its spine rests upright and its hips rest turned
+        world.spawn(RetargetRig {
+            bones: vec![
+                RetargetBone {
+                    entity: hips,
+                    source_rest: Transform::from_xyz(0.0, 1.0, 0.0),
+                    target_rest: Transform::from_xyz(0.0, 2.0, 0.0)
+                        .with_rotation(Quat::from_rotation_x(0.5)),
+                    root: true,
+                },
+                RetargetBone {
+                    entity: spine,
+                    source_rest: Transform::from_xyz(0.0, 0.5, 0.0)
+                        .with_rotation(Quat::from_rotation_z(0.3)),
+                    target_rest: Transform::from_xyz(0.0, 0.8, 0.0),
+                    root: false,
+                },
+            ],
+            targets: HashMap::new(),
+            height_scale: 2.0,
+        });
+
+        world.run_system_once(retarget_pose).unwrap();
+
+        let hips = world.get::<Transform>(hips).unwrap();
+        assert!(hips.translation.abs_diff_eq(Vec3::new(0.0, 2.4, 1.0), 1e-5));
+        assert!(hips.rotation.abs_diff_eq(
+            Quat::from_rotation_x(0.5) * Quat::from_rotation_y(1.0),
+            1e-5
+        ));
+        // Only the bend from the clip rig's rest pose carries over
+        let spine = world.get::<Transform>(spine).unwrap();
+        assert!(
+            spine
+                .translation
+                .abs_diff_eq(Vec3::new(0.0, 0.8, 0.0), 1e-5)
+        );
+        assert!(spine.rotation.abs_diff_eq(Quat::from_rotation_x(0.2), 1e-5));
+    }
+}
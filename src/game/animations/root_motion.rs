@@ -0,0 +1,139 @@
+//! Root motion extraction for clips that move the character through their root bone.
+//!
+//! While a state with `root_motion` is active, the horizontal translation of the root bone is
+//! stripped from the evaluated pose and turned into a velocity that `apply_controls` feeds to the
+//! Tnua controller instead of the player's input. The vertical translation stays in the pose,
+//! since Tnua keeps the collider floating above the ground, unless the character is carried by a
+//! [`MotionWarp`](super::motion_warping::MotionWarp) instead.
+
+use bevy::{app::AnimationSystems, prelude::*, transform::TransformSystems};
+
+use super::{
+    foot_ik::place_feet,
+    skeleton::{Bones, find_bone, parent_world_transform},
+};
+
+/// Largest root bone movement in a frame that is still treated as motion, anything beyond is a
+/// clip restarting or looping
+const MAX_ROOT_STEP: f32 = 0.5;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        PostUpdate,
+        (resolve_root_motion_rigs, extract_root_motion)
+            .chain()
+            .after(AnimationSystems)
+            .before(place_feet)
+            .before(TransformSystems::Propagate),
+    );
+}
+
+/// Extracts root motion from the animation of a character
+///
+/// `active` is set by the animation state machine from the current state.
+#[derive(Component, Debug, Clone)]
+pub struct RootMotion {
+    /// Name of the bone carrying the root motion, defaults to the Mixamo hips
+    pub root_bone: String,
+    /// Whether the current animation state drives the character's movement
+    pub active: bool,
+    /// Horizontal world-space velocity extracted from the root bone during the last frame
+    pub velocity: Vec3,
+    /// Whether the vertical translation is taken out of the pose too, for moves that carry the
+    /// character up and down themselves
+    pub vertical: bool,
+}
+
+impl Default for RootMotion {
+    fn default() -> Self {
+        Self {
+            root_bone: "mixamorig:Hips".to_string(),
+            active: false,
+            velocity: Vec3::ZERO,
+            vertical: false,
+        }
+    }
+}
+
+/// Root bone entity of a [`RootMotion`] character and its translation tracking
+#[derive(Component, Debug)]
+pub(super) struct RootMotionRig {
+    root: Entity,
+    /// Animated root translation when the root motion state was entered, kept in the pose
+    anchor: Option<Vec3>,
+    /// Horizontal world-space distance the root bone had moved from the anchor last frame
+    previous_offset: Vec3,
+}
+
+/// Finds the root bone of characters whose scene has spawned
+fn resolve_root_motion_rigs(
+    mut commands: Commands,
+    characters: Query<(Entity, &RootMotion, &Children), Without<RootMotionRig>>,
+    models: Query<Entity, With<SceneRoot>>,
+    descendants: Query<&Children>,
+    names: Query<&Name>,
+) {
+    for (character, root_motion, children) in &characters {
+        let Some(model) = children.iter().find(|child| models.contains(*child)) else {
+            continue;
+        };
+        let Some(root) = find_bone(model, &root_motion.root_bone, &descendants, &names) else {
+            continue;
+        };
+        commands.entity(character).insert(RootMotionRig {
+            root,
+            anchor: None,
+            previous_offset: Vec3::ZERO,
+        });
+    }
+}
+
+/// Moves the horizontal root bone translation of the pose into [`RootMotion::velocity`]
+pub(super) fn extract_root_motion(
+    mut characters: Query<(&mut RootMotion, &mut RootMotionRig)>,
+    mut bones: Bones,
+    time: Res<Time>,
+) {
+    for (mut root_motion, mut rig) in &mut characters {
+        if !root_motion.active {
+            rig.anchor = None;
+            root_motion.velocity = Vec3::ZERO;
+            continue;
+        }
+        let Ok((transform, _)) = bones.get(rig.root) else {
+            continue;
+        };
+        let animated = transform.translation;
+        let anchor = match rig.anchor {
+            Some(anchor) => anchor,
+            None => {
+                rig.anchor = Some(animated);
+                rig.previous_offset = Vec3::ZERO;
+                animated
+            }
+        };
+
+        // The root bone's local space is usually rotated and scaled, so split off the
+        // horizontal movement in world space
+        let parent = parent_world_transform(rig.root, &bones).compute_affine();
+        let offset = parent.transform_vector3(animated - anchor);
+        let horizontal_offset = Vec3::new(offset.x, 0.0, offset.z);
+
+        let step = horizontal_offset - rig.previous_offset;
+        rig.previous_offset = horizontal_offset;
+        root_motion.velocity = if step.length() <= MAX_ROOT_STEP && time.delta_secs() > 0.0 {
+            step / time.delta_secs()
+        } else {
+            Vec3::ZERO
+        };
+
+        let removed = if root_motion.vertical {
+            offset
+        } else {
+            horizontal_offset
+        };
+        if let Ok((mut transform, _)) = bones.get_mut(rig.root) {
+            transform.translation = animated - parent.inverse().transform_vector3(removed);
+        }
+    }
+}
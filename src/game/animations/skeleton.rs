@@ -0,0 +1,46 @@
+//! Helpers for post-processing the animated skeleton of a character.
+//!
+//! Systems using these run after the animation has posed the bones and before transforms are
+//! propagated, so world transforms are composed from the freshly animated local transforms.
+
+use bevy::prelude::*;
+
+/// Local transforms of the bones, with their parents to compose world transforms
+pub type Bones<'w, 's> = Query<'w, 's, (&'static mut Transform, Option<&'static ChildOf>)>;
+
+/// Finds the descendant of `root` with the given name
+pub fn find_bone(
+    root: Entity,
+    name: &str,
+    descendants: &Query<&Children>,
+    names: &Query<&Name>,
+) -> Option<Entity> {
+    descendants
+        .iter_descendants(root)
+        .find(|entity| names.get(*entity).is_ok_and(|bone| bone.as_str() == name))
+}
+
+/// Composes the world transform of `entity` from the local transforms of its ancestors
+pub fn world_transform(entity: Entity, bones: &Bones) -> Transform {
+    let mut result = Transform::IDENTITY;
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        let Ok((transform, parent)) = bones.get(entity) else {
+            break;
+        };
+        result = transform.mul_transform(result);
+        current = parent.map(ChildOf::parent);
+    }
+    result
+}
+
+/// Composes the world transform of the parent of `entity`
+pub fn parent_world_transform(entity: Entity, bones: &Bones) -> Transform {
+    bones
+        .get(entity)
+        .ok()
+        .and_then(|(_, parent)| parent)
+        .map_or(Transform::IDENTITY, |parent| {
+            world_transform(parent.parent(), bones)
+        })
+}
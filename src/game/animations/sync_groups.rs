@@ -0,0 +1,108 @@
+//! Sync groups keeping the phase of blended looping clips together.
+//!
+//! Clips in a group play at a shared cycle length, the weighted average of their own cycle
+//! lengths, and follow the normalized phase of the heaviest clip. A walk blended halfway into a
+//! run thus takes a stride in between the two, and both clips plant their feet at the same time.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::game::configs::{AnimationBlendingConfig, AnimationStateMachine};
+
+use super::{
+    animation_controller::{AnimationNodes, clip_duration},
+    transitions::StateTransitions,
+};
+
+/// Largest normalized phase difference a clip may drift from its group before it is moved back
+const MAX_PHASE_DRIFT: f32 = 0.01;
+
+/// A playing clip of a sync group
+struct SyncedClip {
+    node: AnimationNodeIndex,
+    duration: f32,
+    /// Seconds one cycle takes at the clip's own playback speed
+    cycle: f32,
+    weight: f32,
+}
+
+/// Matches the playback speed and phase of all playing clips that share a sync group
+///
+/// Runs after the clip weights are set, since the weights decide the cycle length.
+pub fn sync_clip_phases(
+    state_machine: &AnimationStateMachine,
+    animation_nodes: &AnimationNodes,
+    blend_config: &AnimationBlendingConfig,
+    transitions: &StateTransitions,
+    graph: &AnimationGraph,
+    clips: &Assets<AnimationClip>,
+    animation_player: &mut AnimationPlayer,
+) {
+    let mut groups: HashMap<&str, Vec<SyncedClip>> = HashMap::new();
+    for (name, _) in transitions.weights() {
+        let (Some(state), Some(nodes)) = (
+            state_machine.states.get(name),
+            animation_nodes.states.get(name),
+        ) else {
+            continue;
+        };
+        // One-shot clips have no cycle to share
+        let Some(group) = state.sync_group.as_deref().filter(|_| state.looping) else {
+            continue;
+        };
+
+        for (&node, clip) in nodes.iter().zip(state.motion.clips(blend_config)) {
+            let speed = state.speed * clip.playback_speed;
+            let (true, Some(animation), Some(duration)) = (
+                clip.sync,
+                animation_player.animation(node),
+                clip_duration(node, graph, clips),
+            ) else {
+                continue;
+            };
+            if duration <= 0.0 || speed <= 0.0 {
+                continue;
+            }
+            groups.entry(group).or_default().push(SyncedClip {
+                node,
+                duration,
+                cycle: duration / speed,
+                weight: animation.weight(),
+            });
+        }
+    }
+
+    for group in groups.values() {
+        let total_weight: f32 = group.iter().map(|clip| clip.weight).sum();
+        let Some(leader) = group.iter().max_by(|a, b| a.weight.total_cmp(&b.weight)) else {
+            continue;
+        };
+        if total_weight <= 0.0 {
+            continue;
+        }
+
+        let cycle = group
+            .iter()
+            .map(|clip| clip.weight * clip.cycle)
+            .sum::<f32>()
+            / total_weight;
+        let phase = animation_player
+            .animation(leader.node)
+            .map_or(0.0, |animation| animation.seek_time() / leader.duration);
+
+        for clip in group {
+            let Some(animation) = animation_player.animation_mut(clip.node) else {
+                continue;
+            };
+            animation.set_speed(clip.duration / cycle);
+
+            // Clips joining the group mid-cycle jump to its phase, without firing the markers
+            // they skip over
+            let drift = (animation.seek_time() / clip.duration - phase).rem_euclid(1.0);
+            if drift.min(1.0 - drift) > MAX_PHASE_DRIFT {
+                animation.set_seek_time(phase * clip.duration);
+            }
+        }
+    }
+}
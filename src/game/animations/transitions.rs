@@ -0,0 +1,91 @@
+//! Cross-fades between animation state machine states.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+/// Cross-fades between state machine states on an [`AnimationPlayer`]
+///
+/// [`AnimationTransitions`] only fades single clips, but a state can drive several clips at once
+/// (a blend space), so the fade is tracked per state and multiplied into its clip weights.
+#[derive(Component, Default, Debug)]
+pub struct StateTransitions {
+    /// States being blended, oldest first. The last one is the active state.
+    layers: Vec<StateLayer>,
+}
+
+#[derive(Debug)]
+struct StateLayer {
+    state: String,
+    /// Fade-in progress of the state, from 0.0 to 1.0
+    fade: f32,
+    /// Fade-in duration in seconds
+    duration: f32,
+    /// Weight left for this state after newer states took their share
+    weight: f32,
+}
+
+impl StateTransitions {
+    /// Starts fading in `state` over `duration`, fading out every other state
+    pub fn play(&mut self, state: &str, duration: Duration) {
+        // Re-entering a state that is still fading out resumes from its current weight
+        let fade = self
+            .layers
+            .iter()
+            .position(|layer| layer.state == state)
+            .map_or(0.0, |index| self.layers.remove(index).weight);
+
+        self.layers.push(StateLayer {
+            state: state.to_string(),
+            fade,
+            duration: duration.as_secs_f32(),
+            weight: 0.0,
+        });
+    }
+
+    /// Advances the cross-fade and returns the states that have completely faded out
+    pub fn advance(&mut self, delta_secs: f32) -> Vec<String> {
+        if let Some(active) = self.layers.last_mut() {
+            active.fade = if active.duration > 0.0 {
+                (active.fade + delta_secs / active.duration).min(1.0)
+            } else {
+                1.0
+            };
+        }
+
+        // The newest state takes as much weight as it wants and older states share the rest
+        let mut remaining = 1.0;
+        for layer in self.layers.iter_mut().rev() {
+            layer.weight = layer.fade * remaining;
+            remaining -= layer.weight;
+        }
+        if let Some(oldest) = self.layers.first_mut() {
+            oldest.weight += remaining;
+        }
+
+        let active_count = self.layers.len().saturating_sub(1);
+        let mut faded_out = Vec::new();
+        let mut index = 0;
+        self.layers.retain(|layer| {
+            let keep = index == active_count || layer.weight > 0.0;
+            index += 1;
+            if !keep {
+                faded_out.push(layer.state.clone());
+            }
+            keep
+        });
+        faded_out
+    }
+
+    /// Returns the fade-in progress of the active state, 1.0 once the transition has finished
+    pub fn progress(&self) -> f32 {
+        self.layers.last().map_or(1.0, |layer| layer.fade)
+    }
+
+    /// Returns every blended state with its current weight
+    pub fn weights(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.layers
+            .iter()
+            .map(|layer| (layer.state.as_str(), layer.weight))
+    }
+}
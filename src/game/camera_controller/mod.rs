@@ -46,6 +46,9 @@ fn attach_camera_to_player(
 
                     cursor_lock_key: KeyCode::KeyL,
                     cursor_lock_active: true, // Start with cursor locked
+
+                    // Hold right mouse to aim, the player faces the camera and strafes
+                    aim_enabled: true,
                     ..default()
                 },
                 Projection::from(PerspectiveProjection {
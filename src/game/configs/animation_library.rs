@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext},
+    gltf::Gltf,
+    prelude::*,
+};
+use serde::Deserialize;
+
+/// Animation clips loaded from separate GLB files, keyed by logical name
+///
+/// The GLB files are dependencies of the library, so it is only inserted as a resource once
+/// every file has loaded. Files that don't exist are left out with a warning.
+#[derive(Asset, Resource, TypePath, Clone, Debug)]
+pub struct AnimationLibrary {
+    #[dependency]
+    files: Vec<Handle<Gltf>>,
+    entries: HashMap<String, LibraryClip>,
+}
+
+/// A clip of the [`AnimationLibrary`]
+#[derive(Clone, Debug)]
+struct LibraryClip {
+    file: Handle<Gltf>,
+    /// Name of the clip in the file, the first clip of the file if empty
+    clip: Option<String>,
+}
+
+/// RON manifest of an [`AnimationLibrary`]
+#[derive(Deserialize)]
+struct AnimationLibraryManifest {
+    /// `path/to/file.glb#Clip Name` for every logical clip name, the clip name is optional
+    clips: HashMap<String, String>,
+}
+
+impl AnimationLibrary {
+    /// Path to the animation library manifest
+    pub const PATH: &'static str = "config/animation_library.ron";
+
+    /// Returns every clip of the library, keyed by logical name
+    ///
+    /// Clips missing from their file are logged and left out.
+    pub fn clips(&self, gltfs: &Assets<Gltf>) -> HashMap<String, Handle<AnimationClip>> {
+        self.entries
+            .iter()
+            .filter_map(|(name, entry)| {
+                let gltf = gltfs.get(&entry.file)?;
+                let clip = match &entry.clip {
+                    Some(clip) => gltf.named_animations.get(clip.as_str()),
+                    None => gltf.animations.first(),
+                };
+                if clip.is_none() {
+                    error!(
+                        "Library clip `{name}` not found, available clips: {:?}",
+                        gltf.named_animations.keys().collect::<Vec<_>>()
+                    );
+                }
+                Some((name.clone(), clip?.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Asset loader for AnimationLibrary RON manifests
+pub struct AnimationLibraryLoader {
+    asset_server: AssetServer,
+}
+
+impl FromWorld for AnimationLibraryLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            asset_server: world.resource::<AssetServer>().clone(),
+        }
+    }
+}
+
+impl AnimationLibraryLoader {
+    /// Whether the file at `path` exists in its asset source
+    async fn exists(&self, path: &str) -> bool {
+        let path = AssetPath::parse(path);
+        let Ok(source) = self.asset_server.get_source(path.source()) else {
+            return false;
+        };
+        source.reader().read(path.path()).await.is_ok()
+    }
+}
+
+impl AssetLoader for AnimationLibraryLoader {
+    type Asset = AnimationLibrary;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn bevy::asset::io::Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let manifest: AnimationLibraryManifest = ron::de::from_bytes(&bytes)?;
+
+        // Every file is loaded once, however many clips come from it. A missing file would never
+        // finish loading and hold up the library, so it is skipped.
+        let mut files: HashMap<String, Option<Handle<Gltf>>> = HashMap::new();
+        let mut entries = HashMap::new();
+        for (name, source) in manifest.clips {
+            let (path, clip) = match source.rsplit_once('#') {
+                Some((path, clip)) => (path.to_string(), Some(clip.to_string())),
+                None => (source, None),
+            };
+            if !files.contains_key(&path) {
+                let file = if self.exists(&path).await {
+                    Some(load_context.load(path.clone()))
+                } else {
+                    warn!("Animation library file `{path}` not found, skipping its clips");
+                    None
+                };
+                files.insert(path.clone(), file);
+            }
+            if let Some(file) = files[&path].clone() {
+                entries.insert(name, LibraryClip { file, clip });
+            }
+        }
+
+        Ok(AnimationLibrary {
+            files: files.into_values().flatten().collect(),
+            entries,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use bevy::{
     asset::{AssetLoader, AsyncReadExt, LoadContext},
     prelude::*,
@@ -12,6 +14,29 @@ pub struct AnimationBlendingConfig {
     /// Animation assignments for different movement states
     #[serde(default)]
     pub animations: AnimationAssignments,
+    /// Sample points of the speed-driven locomotion blend space
+    ///
+    /// When empty, the samples are derived from `speed_thresholds`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locomotion: Vec<LocomotionSample>,
+    /// Sample points of the 2D directional blend space used while aiming
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directional: Vec<DirectionalSample>,
+    /// Timed event markers, keyed by the name of the clip they belong to
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub markers: HashMap<String, Vec<AnimationMarker>>,
+    /// Procedural body lean while turning and accelerating
+    #[serde(default)]
+    pub lean: LeanConfig,
+    /// Procedural head and upper spine look-at
+    #[serde(default)]
+    pub look_at: LookAtConfig,
+    /// Timing and hand placement of the vault clip, for warping vaults onto obstacles
+    #[serde(default)]
+    pub vault: VaultConfig,
+    /// Timing and hand placement of the climbing clips, for warping climbs onto obstacles
+    #[serde(default)]
+    pub climb: ClimbConfig,
 }
 
 /// Animation assignments for different movement states
@@ -27,6 +52,19 @@ pub struct AnimationAssignments {
     pub jump: Option<String>,
 }
 
+impl AnimationAssignments {
+    /// Returns the clip assigned to a logical slot (`idle`, `walk`, `run` or `jump`)
+    pub fn get(&self, slot: &str) -> Option<&str> {
+        match slot {
+            "idle" => self.idle.as_deref(),
+            "walk" => self.walk.as_deref(),
+            "run" => self.run.as_deref(),
+            "jump" => self.jump.as_deref(),
+            _ => None,
+        }
+    }
+}
+
 impl Default for AnimationAssignments {
     fn default() -> Self {
         Self {
@@ -49,9 +87,258 @@ pub struct SpeedThresholds {
     pub run_speed: f32,
 }
 
+/// A clip of the locomotion blend space, at full weight when moving at `speed`
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct LocomotionSample {
+    /// Name of the animation clip in the player GLTF
+    pub clip: String,
+    /// Movement speed at which this clip is at 100%
+    pub speed: f32,
+    /// Playback speed multiplier of the clip
+    #[serde(default = "default_playback_speed")]
+    pub playback_speed: f32,
+    /// Whether the clip follows the phase of its state's sync group, off for e.g. idle clips
+    /// whose cycle has nothing to do with the walk cycle
+    #[serde(default = "default_sync")]
+    pub sync: bool,
+}
+
+/// A clip of the directional blend space, at full weight when moving at the sample's velocity
+///
+/// The velocity is relative to the character's facing. A sample at zero velocity is the center of
+/// the blend space (usually idle), all other samples should lie on a ring around it.
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct DirectionalSample {
+    /// Name of the animation clip in the player GLTF
+    pub clip: String,
+    /// Velocity to the character's right at which this clip is at 100%
+    #[serde(default)]
+    pub right: f32,
+    /// Velocity to the character's front at which this clip is at 100%
+    #[serde(default)]
+    pub forward: f32,
+    /// Playback speed multiplier of the clip
+    #[serde(default = "default_playback_speed")]
+    pub playback_speed: f32,
+    /// Whether the clip follows the phase of its state's sync group, off for e.g. idle clips
+    /// whose cycle has nothing to do with the walk cycle
+    #[serde(default = "default_sync")]
+    pub sync: bool,
+}
+
+impl DirectionalSample {
+    /// Returns the sample velocity, `x` pointing right and `y` forward
+    pub fn velocity(&self) -> Vec2 {
+        Vec2::new(self.right, self.forward)
+    }
+}
+
+/// An event fired when a clip's playhead crosses the marker's position
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationMarker {
+    /// Position in the clip, from 0.0 (start) to 1.0 (end), so markers survive re-timed clips
+    pub phase: f32,
+    /// Name of the marker, e.g. "footstep"
+    pub name: String,
+}
+
+/// Procedural lean of the upper body into turns and against acceleration
+///
+/// Angles are in degrees, positive leaning right and forward.
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeanConfig {
+    /// Bones tilted by the lean, with their share of the total angle
+    pub bones: Vec<LeanBone>,
+    /// Sideways lean per unit of turning, i.e. per rad/s of turning at 1 m/s
+    pub turn_lean: f32,
+    /// Lean per m/s² of horizontal acceleration
+    pub acceleration_lean: f32,
+    /// Largest sideways lean
+    pub max_side_angle: f32,
+    /// Largest forward or backward lean
+    pub max_forward_angle: f32,
+    /// Spring stiffness pulling the lean toward its target
+    pub stiffness: f32,
+    /// Spring damping, `2 * sqrt(stiffness)` settles without overshooting
+    pub damping: f32,
+}
+
+/// A bone turned by the [`LeanConfig`] or [`LookAtConfig`]
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct LeanBone {
+    /// Bone name in the character rig
+    pub bone: String,
+    /// Share of the total angle applied to this bone
+    pub weight: f32,
+}
+
+impl Default for LeanConfig {
+    fn default() -> Self {
+        let bone = |bone: &str, weight| LeanBone {
+            bone: bone.to_string(),
+            weight,
+        };
+        Self {
+            bones: vec![
+                bone("mixamorig:Hips", 0.3),
+                bone("mixamorig:Spine", 0.3),
+                bone("mixamorig:Spine1", 0.2),
+                bone("mixamorig:Spine2", 0.2),
+            ],
+            turn_lean: 0.6,
+            acceleration_lean: 0.3,
+            max_side_angle: 15.0,
+            max_forward_angle: 10.0,
+            stiffness: 80.0,
+            damping: 14.0,
+        }
+    }
+}
+
+/// Procedural turn of the head and upper spine toward a look-at target
+///
+/// Angles are in degrees, relative to the character's facing direction.
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LookAtConfig {
+    /// Bones turned toward the target, with their share of the total angle
+    pub bones: Vec<LeanBone>,
+    /// Largest turn to either side
+    pub max_yaw: f32,
+    /// Largest turn up or down
+    pub max_pitch: f32,
+    /// How fast the look-at fades in and out, in weight per second
+    pub blend_speed: f32,
+    /// How fast the head follows a moving target, higher is snappier
+    pub follow_speed: f32,
+    /// Distance of the aim point when the camera ray hits nothing
+    pub max_distance: f32,
+}
+
+impl Default for LookAtConfig {
+    fn default() -> Self {
+        let bone = |bone: &str, weight| LeanBone {
+            bone: bone.to_string(),
+            weight,
+        };
+        Self {
+            bones: vec![
+                bone("mixamorig:Spine2", 0.2),
+                bone("mixamorig:Neck", 0.3),
+                bone("mixamorig:Head", 0.5),
+            ],
+            max_yaw: 70.0,
+            max_pitch: 40.0,
+            blend_speed: 3.0,
+            follow_speed: 10.0,
+            max_distance: 50.0,
+        }
+    }
+}
+
+/// Where the vault clip puts its hand on the obstacle, so the vault can be warped to bring the
+/// hand onto the obstacle's top edge
+///
+/// Times are in seconds into the clip. Distances are in meters from the character's center, along
+/// its facing direction and up.
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VaultConfig {
+    /// When the hand touches the top of the obstacle
+    pub contact_time: f32,
+    /// When the feet are back on the ground
+    pub land_time: f32,
+    /// How far ahead of the center the hand is at contact
+    pub hand_forward: f32,
+    /// How far above the center the hand is at contact
+    pub hand_height: f32,
+    /// How high the center passes over the obstacle's top
+    pub clearance: f32,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        Self {
+            contact_time: 0.35,
+            land_time: 0.9,
+            hand_forward: 0.4,
+            hand_height: 0.1,
+            clearance: 0.6,
+        }
+    }
+}
+
+/// Where the climbing clips hang from the top edge of a wall, so climbs can be warped to bring
+/// the hands onto the edge
+///
+/// Times are in seconds into the clips. Distances are in meters from the top edge to the
+/// character's center, except for `top_forward`.
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClimbConfig {
+    /// When the hands grab the top edge, jumping up to hang or climbing over a wall
+    pub grab_time: f32,
+    /// When the feet are level with the top, climbing up from the hands on the edge
+    pub pull_up_time: f32,
+    /// When the character stands on the top, climbing up from the hands on the edge
+    pub climb_up_time: f32,
+    /// How far below the edge the center hangs
+    pub hang_height: f32,
+    /// How far back from the edge the center hangs
+    pub hang_offset: f32,
+    /// How far past the edge the center ends up on the top
+    pub top_forward: f32,
+}
+
+impl Default for ClimbConfig {
+    fn default() -> Self {
+        Self {
+            grab_time: 0.5,
+            pull_up_time: 0.8,
+            climb_up_time: 1.4,
+            hang_height: 1.1,
+            hang_offset: 0.55,
+            top_forward: 0.6,
+        }
+    }
+}
+
+fn default_playback_speed() -> f32 {
+    1.0
+}
+
+fn default_sync() -> bool {
+    true
+}
+
 impl AnimationBlendingConfig {
     /// Path to the animation blending configuration file
     pub const PATH: &'static str = "config/animation_blending.ron";
+
+    /// Returns the locomotion blend space samples, sorted by speed
+    pub fn locomotion_samples(&self) -> Vec<LocomotionSample> {
+        let mut samples = if self.locomotion.is_empty() {
+            let thresholds = &self.speed_thresholds;
+            [
+                ("idle", thresholds.idle_threshold),
+                ("walk", thresholds.walk_speed),
+                ("running", thresholds.run_speed),
+            ]
+            .into_iter()
+            .map(|(clip, speed)| LocomotionSample {
+                clip: clip.to_string(),
+                speed,
+                playback_speed: 1.0,
+                sync: clip != "idle",
+            })
+            .collect()
+        } else {
+            self.locomotion.clone()
+        };
+        samples.sort_by(|a, b| a.speed.total_cmp(&b.speed));
+        samples
+    }
 }
 
 impl Default for AnimationBlendingConfig {
@@ -63,6 +350,13 @@ impl Default for AnimationBlendingConfig {
                 run_speed: 8.0,
             },
             animations: AnimationAssignments::default(),
+            locomotion: Vec::new(),
+            directional: Vec::new(),
+            markers: HashMap::new(),
+            lean: LeanConfig::default(),
+            look_at: LookAtConfig::default(),
+            vault: VaultConfig::default(),
+            climb: ClimbConfig::default(),
         }
     }
 }
@@ -0,0 +1,72 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::bail;
+use bevy::{
+    asset::{AssetLoader, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+/// Maps the bones of a character rig to the rig the animation clips were authored on
+///
+/// Loaded from RON files, e.g.
+/// ```ron
+/// (
+///     root: "mixamorig:Hips",
+///     bones: {
+///         "mixamorig:Hips": "Hips",
+///         "mixamorig:Spine": "Spine",
+///     },
+/// )
+/// ```
+#[derive(Asset, Reflect, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BoneMap {
+    /// Bone of the clip rig carrying the root translation, which is scaled to the character's
+    /// height. All other bones only take rotations from the clips.
+    pub root: String,
+    /// Character bone name for every bone name of the clip rig
+    pub bones: HashMap<String, String>,
+}
+
+impl BoneMap {
+    /// Checks that the root is mapped and no character bone is mapped twice
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.bones.contains_key(&self.root) {
+            bail!("root bone `{}` is not mapped", self.root);
+        }
+        let mut targets = HashSet::new();
+        for target in self.bones.values() {
+            if !targets.insert(target) {
+                bail!("bone `{target}` is mapped more than once");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Asset loader for BoneMap RON files
+#[derive(Default)]
+pub struct BoneMapLoader;
+
+impl AssetLoader for BoneMapLoader {
+    type Asset = BoneMap;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn bevy::asset::io::Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let bone_map: BoneMap = ron::de::from_bytes(&bytes)?;
+        bone_map.validate()?;
+        Ok(bone_map)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
@@ -1,16 +1,40 @@
+pub mod animation_library;
 pub mod assets;
+pub mod bone_map;
+pub mod state_machine;
 
 use bevy::prelude::*;
 
 use crate::asset_tracking::LoadResource;
 
-pub use assets::{AnimationAssignments, AnimationBlendingConfig, AnimationBlendingConfigLoader};
+pub use animation_library::{AnimationLibrary, AnimationLibraryLoader};
+pub use assets::{
+    AnimationAssignments, AnimationBlendingConfig, AnimationBlendingConfigLoader, AnimationMarker,
+    ClimbConfig, DirectionalSample, LocomotionSample, VaultConfig,
+};
+pub use bone_map::{BoneMap, BoneMapLoader};
+pub use state_machine::{
+    AnimationStateMachine, AnimationStateMachineLoader, JumpPhase, MotionClip, StateMotion,
+    TransitionCondition, TransitionMode,
+};
 
 pub(super) fn plugin(app: &mut App) {
     // Register the asset loader for RON config files
     app.init_asset::<AnimationBlendingConfig>();
     app.init_asset_loader::<AnimationBlendingConfigLoader>();
+    app.init_asset::<AnimationStateMachine>();
+    app.init_asset_loader::<AnimationStateMachineLoader>();
+    app.init_asset::<BoneMap>();
+    app.init_asset_loader::<BoneMapLoader>();
+    app.init_asset::<AnimationLibrary>();
+    app.init_asset_loader::<AnimationLibraryLoader>();
 
-    // Load animation blending configuration
-    app.load_resource::<AnimationBlendingConfig>();
+    // Load animation blending configuration, reloaded while the game runs
+    app.load_live_resource_from_path::<AnimationBlendingConfig>(AnimationBlendingConfig::PATH);
+
+    // Load the player animation state machine, reloaded while the game runs
+    app.load_live_resource_from_path::<AnimationStateMachine>(AnimationStateMachine::PATH);
+
+    // Load the clips kept in separate GLB files
+    app.load_resource_from_path::<AnimationLibrary>(AnimationLibrary::PATH);
 }
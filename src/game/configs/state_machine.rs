@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, bail};
+use bevy::{
+    asset::{AssetLoader, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use super::AnimationBlendingConfig;
+
+/// Animation state machine loaded from a RON file
+///
+/// Every state names the motion it plays. Transitions are checked in order every frame and the
+/// first one whose conditions all hold is taken, so more specific transitions should be listed
+/// before general ones.
+#[derive(Asset, Resource, Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationStateMachine {
+    /// State a character starts in
+    pub initial_state: String,
+    /// All states, keyed by name
+    pub states: HashMap<String, AnimationStateDefinition>,
+    /// Transitions between states, checked in order
+    #[serde(default)]
+    pub transitions: Vec<AnimationTransitionDefinition>,
+    /// Bone groups that layers can be limited to, keyed by name
+    ///
+    /// Each group lists bones that are included with all their descendants. A bone belongs to the
+    /// group of its nearest listed ancestor, so groups can be nested (e.g. an arm inside the upper
+    /// body) without overlapping.
+    #[serde(default)]
+    pub mask_groups: HashMap<String, Vec<String>>,
+    /// Clips that play over the states on a single mask group, keyed by name
+    #[serde(default)]
+    pub layers: HashMap<String, AnimationLayerDefinition>,
+    /// Files below `assets` whose states, transitions, mask groups and layers are added to this
+    /// machine when it loads, e.g. states playing clips that are not always available
+    ///
+    /// Transitions of included files are checked before the machine's own.
+    #[serde(default)]
+    pub includes: Vec<String>,
+}
+
+/// Part of an [`AnimationStateMachine`] kept in a file of its own, see
+/// [`AnimationStateMachine::includes`]
+#[derive(Clone, Debug, Default, Deserialize)]
+struct IncludedStateMachine {
+    #[serde(default)]
+    states: HashMap<String, AnimationStateDefinition>,
+    #[serde(default)]
+    transitions: Vec<AnimationTransitionDefinition>,
+    #[serde(default)]
+    mask_groups: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    layers: HashMap<String, AnimationLayerDefinition>,
+}
+
+/// A single state of the [`AnimationStateMachine`]
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationStateDefinition {
+    /// What the state plays
+    pub motion: StateMotion,
+    /// Playback speed multiplier
+    #[serde(default = "default_playback_speed")]
+    pub speed: f32,
+    /// Whether the motion loops while the state is active
+    #[serde(default)]
+    pub looping: bool,
+    /// Whether the root bone's horizontal movement in the clips moves the character instead of
+    /// the player's input
+    #[serde(default)]
+    pub root_motion: bool,
+    /// Whether the player's movement input is ignored while the state is active
+    #[serde(default)]
+    pub lock_movement: bool,
+    /// Whether the character turns toward the input direction over the length of the clip,
+    /// instead of at the controller's turning rate (turn in place, pivots)
+    #[serde(default)]
+    pub sync_turn: bool,
+    /// Sync group of the state's clips
+    ///
+    /// All playing clips in a group share a normalized phase, e.g. so the feet of walk and run
+    /// clips hit the ground together while blending, and play at the blended cycle length.
+    #[serde(default)]
+    pub sync_group: Option<String>,
+}
+
+fn default_playback_speed() -> f32 {
+    1.0
+}
+
+/// The animation a state plays
+#[derive(Reflect, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StateMotion {
+    /// A single animation clip from the player GLTF
+    Clip(String),
+    /// The speed-driven locomotion blend space of the [`AnimationBlendingConfig`]
+    Locomotion,
+    /// The velocity-driven directional blend space of the [`AnimationBlendingConfig`]
+    Directional,
+}
+
+/// A clip played by a [`StateMotion`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotionClip {
+    /// Name of the animation clip in the player GLTF
+    pub name: String,
+    /// Playback speed multiplier
+    pub playback_speed: f32,
+    /// Whether the clip follows the state's sync group
+    pub sync: bool,
+}
+
+impl StateMotion {
+    /// Returns the clips this motion plays
+    pub fn clips(&self, blend_config: &AnimationBlendingConfig) -> Vec<MotionClip> {
+        match self {
+            Self::Clip(clip) => vec![MotionClip {
+                name: clip.clone(),
+                playback_speed: 1.0,
+                sync: true,
+            }],
+            Self::Locomotion => blend_config
+                .locomotion_samples()
+                .into_iter()
+                .map(|sample| MotionClip {
+                    name: sample.clip,
+                    playback_speed: sample.playback_speed,
+                    sync: sample.sync,
+                })
+                .collect(),
+            Self::Directional => blend_config
+                .directional
+                .iter()
+                .map(|sample| MotionClip {
+                    name: sample.clip.clone(),
+                    playback_speed: sample.playback_speed,
+                    sync: sample.sync,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A clip layered over the state machine, e.g. waving while running
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationLayerDefinition {
+    /// Animation clip from the player GLTF
+    pub clip: String,
+    /// Name of the mask group the clip is limited to
+    pub mask: String,
+    /// Playback speed multiplier
+    #[serde(default = "default_playback_speed")]
+    pub speed: f32,
+    /// Whether the clip loops while the layer is active, otherwise the layer stops at its end
+    #[serde(default)]
+    pub looping: bool,
+    /// Fade in and out duration in seconds
+    #[serde(default)]
+    pub blend_duration: f32,
+    /// Whether the clip is added on top of the pose instead of replacing it on its mask group
+    ///
+    /// Additive layers play the clip's offsets from the [`reference`](Self::reference) pose at
+    /// the layer's weight on top of everything else.
+    #[serde(default)]
+    pub additive: bool,
+    /// Clip whose first frame additive layers measure their clip's offsets against
+    ///
+    /// Without one, the clip's own first frame is the reference, so only its motion is added.
+    #[serde(default)]
+    pub reference: Option<String>,
+}
+
+/// A transition edge between states of the [`AnimationStateMachine`]
+#[derive(Reflect, Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationTransitionDefinition {
+    /// States this transition can be taken from, [`ANY_STATE`] matches every state
+    pub from: Vec<String>,
+    /// State to switch to
+    pub to: String,
+    /// Cross-fade or inertialization duration in seconds
+    #[serde(default)]
+    pub blend_duration: f32,
+    /// How the previous state's pose blends into the new one
+    #[serde(default)]
+    pub mode: TransitionMode,
+    /// Conditions that must all hold for the transition to be taken
+    #[serde(default)]
+    pub conditions: Vec<TransitionCondition>,
+}
+
+/// How a transition blends from the previous state's pose
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransitionMode {
+    /// Plays both states and fades their weights over the blend duration
+    #[default]
+    CrossFade,
+    /// Switches to the new state at once and decays the pose difference at the switch over
+    /// the blend duration, for sharp changes like jump starts
+    Inertialize,
+}
+
+/// A condition on the character's movement checked by a transition
+#[derive(Reflect, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TransitionCondition {
+    /// Horizontal speed is at least this value
+    SpeedAbove(f32),
+    /// Horizontal speed is below this value
+    SpeedBelow(f32),
+    /// Character is standing on the ground
+    Grounded,
+    /// Character is in the air
+    Airborne,
+    /// The named Tnua action is active
+    Action(String),
+    /// No Tnua action is active
+    NoAction,
+    /// The player is holding the camera's aim button
+    Aiming,
+    /// The player is not aiming
+    NotAiming,
+    /// The character is crouched or crouching down
+    Crouching,
+    /// The character is standing or standing up
+    NotCrouching,
+    /// Vertical speed is at least this value, positive is up
+    VerticalSpeedAbove(f32),
+    /// Vertical speed is below this value, positive is up
+    VerticalSpeedBelow(f32),
+    /// A jump action is active and in the given phase
+    JumpPhase(JumpPhase),
+    /// Angle in degrees from the facing to the input direction is at least this value,
+    /// positive to the left
+    TurnAngleAbove(f32),
+    /// Angle in degrees from the facing to the input direction is below this value,
+    /// positive to the left
+    TurnAngleBelow(f32),
+    /// Downward speed when the character last touched the ground is at least this value
+    ImpactAbove(f32),
+    /// Downward speed when the character last touched the ground is below this value
+    ImpactBelow(f32),
+    /// The motion of the current state has played to the end
+    Finished,
+}
+
+/// Phase of a jump, from the state of Tnua's jump action
+#[derive(Reflect, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JumpPhase {
+    /// Pushing off and going up while the jump button is held
+    Rise,
+    /// The jump button was released or the peak was reached, still going up
+    Apex,
+    /// Falling back down
+    Fall,
+}
+
+/// Wildcard for [`AnimationTransitionDefinition::from`] that matches every state
+pub const ANY_STATE: &str = "*";
+
+impl AnimationStateMachine {
+    /// Path to the player animation state machine
+    pub const PATH: &'static str = "config/state_machines/player.ron";
+
+    /// Returns the transitions that can be taken from `state`, in priority order
+    pub fn transitions_from<'a>(
+        &'a self,
+        state: &'a str,
+    ) -> impl Iterator<Item = &'a AnimationTransitionDefinition> {
+        self.transitions.iter().filter(move |transition| {
+            transition.to != state
+                && transition
+                    .from
+                    .iter()
+                    .any(|from| from == state || from == ANY_STATE)
+        })
+    }
+
+    /// Adds the parts of included files, in the order they are listed
+    fn include(&mut self, included: Vec<IncludedStateMachine>) -> anyhow::Result<()> {
+        let mut transitions = Vec::new();
+        for part in included {
+            for (name, state) in part.states {
+                if self.states.insert(name.clone(), state).is_some() {
+                    bail!("state `{name}` is defined more than once");
+                }
+            }
+            for (name, bones) in part.mask_groups {
+                if self.mask_groups.insert(name.clone(), bones).is_some() {
+                    bail!("mask group `{name}` is defined more than once");
+                }
+            }
+            for (name, layer) in part.layers {
+                if self.layers.insert(name.clone(), layer).is_some() {
+                    bail!("layer `{name}` is defined more than once");
+                }
+            }
+            transitions.extend(part.transitions);
+        }
+        transitions.append(&mut self.transitions);
+        self.transitions = transitions;
+        Ok(())
+    }
+
+    /// Checks that every state referenced by the machine is defined
+    fn validate(&self) -> anyhow::Result<()> {
+        if !self.states.contains_key(&self.initial_state) {
+            bail!("initial state `{}` is not defined", self.initial_state);
+        }
+        for transition in &self.transitions {
+            if !self.states.contains_key(&transition.to) {
+                bail!("transition targets unknown state `{}`", transition.to);
+            }
+            for from in &transition.from {
+                if from != ANY_STATE && !self.states.contains_key(from) {
+                    bail!("transition starts from unknown state `{from}`");
+                }
+            }
+        }
+        // Bevy supports 64 mask groups, the last one holds every bone outside the named groups
+        if self.mask_groups.len() > 63 {
+            bail!("at most 63 mask groups are supported");
+        }
+        for (name, layer) in &self.layers {
+            if !self.mask_groups.contains_key(&layer.mask) {
+                bail!("layer `{name}` uses unknown mask group `{}`", layer.mask);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Asset loader for AnimationStateMachine RON files
+#[derive(Default)]
+pub struct AnimationStateMachineLoader;
+
+impl AssetLoader for AnimationStateMachineLoader {
+    type Asset = AnimationStateMachine;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn bevy::asset::io::Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut state_machine: AnimationStateMachine = ron::de::from_bytes(&bytes)?;
+        // Reading through the load context reloads the machine when an included file changes
+        let mut included = Vec::new();
+        for path in &state_machine.includes {
+            let bytes = load_context.read_asset_bytes(path.clone()).await?;
+            included.push(
+                ron::de::from_bytes(&bytes).with_context(|| format!("in included `{path}`"))?,
+            );
+        }
+        state_machine.include(included)?;
+        state_machine.validate()?;
+        Ok(state_machine)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &str) -> Vec<u8> {
+        std::fs::read(format!("{}/assets/{path}", env!("CARGO_MANIFEST_DIR"))).unwrap()
+    }
+
+    #[test]
+    fn player_state_machine_includes_library_states() {
+        let mut state_machine: AnimationStateMachine =
+            ron::de::from_bytes(&read(AnimationStateMachine::PATH)).unwrap();
+        state_machine.validate().unwrap();
+        let own_transitions = state_machine.transitions.len();
+
+        let library: IncludedStateMachine =
+            ron::de::from_bytes(&read("config/state_machines/player_library.ron")).unwrap();
+        let library_transitions = library.transitions.len();
+        state_machine.include(vec![library]).unwrap();
+        state_machine.validate().unwrap();
+
+        assert!(state_machine.states.contains_key("fall"));
+        assert_eq!(
+            state_machine.transitions.len(),
+            own_transitions + library_transitions
+        );
+        assert_eq!(state_machine.transitions[0].to, "air_jump");
+    }
+
+    #[test]
+    fn include_rejects_duplicate_states() {
+        let mut state_machine: AnimationStateMachine =
+            ron::de::from_bytes(&read(AnimationStateMachine::PATH)).unwrap();
+        let duplicate: IncludedStateMachine =
+            ron::de::from_str(r#"(states: {"locomotion": (motion: Locomotion)})"#).unwrap();
+        assert!(state_machine.include(vec![duplicate]).is_err());
+    }
+
+    fn parse(source: &str) -> AnimationStateMachine {
+        ron::de::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn validate_accepts_known_states() {
+        let state_machine = parse(
+            r#"(
+                initial_state: "idle",
+                states: {"idle": (motion: Locomotion), "jump": (motion: Clip("jump"))},
+                transitions: [
+                    (from: ["idle"], to: "jump"),
+                    (from: ["*"], to: "idle"),
+                ],
+                mask_groups: {"upper_body": ["Spine"]},
+                layers: {"wave": (clip: "wave", mask: "upper_body")},
+            )"#,
+        );
+        state_machine.validate().unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_unknown_states() {
+        let unknown_initial =
+            parse(r#"(initial_state: "run", states: {"idle": (motion: Locomotion)})"#);
+        assert!(unknown_initial.validate().is_err());
+
+        let unknown_target = parse(
+            r#"(
+                initial_state: "idle",
+                states: {"idle": (motion: Locomotion)},
+                transitions: [(from: ["idle"], to: "jump")],
+            )"#,
+        );
+        assert!(unknown_target.validate().is_err());
+
+        let unknown_source = parse(
+            r#"(
+                initial_state: "idle",
+                states: {"idle": (motion: Locomotion)},
+                transitions: [(from: ["jump"], to: "idle")],
+            )"#,
+        );
+        assert!(unknown_source.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_layers_on_unknown_mask_groups() {
+        let state_machine = parse(
+            r#"(
+                initial_state: "idle",
+                states: {"idle": (motion: Locomotion)},
+                layers: {"wave": (clip: "wave", mask: "upper_body")},
+            )"#,
+        );
+        assert!(state_machine.validate().is_err());
+    }
+}
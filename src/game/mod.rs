@@ -2,10 +2,10 @@
 
 mod camera_controller;
 pub mod configs;
-mod player;
+pub mod player;
 mod scene;
 pub mod third_person_camera;
-mod animations;
+pub mod animations;
 
 use bevy::prelude::*;
 
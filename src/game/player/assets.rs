@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+
 use bevy::{gltf::Gltf, prelude::*};
 
+use crate::game::configs::{AnimationBlendingConfig, AnimationLibrary};
+
 /// Resource holding the main player GLTF (contains both model and animations)
 #[derive(Resource, Asset, Reflect, Clone)]
 pub struct PlayerGltfAsset {
@@ -30,6 +34,8 @@ pub struct PlayerAssets {
     pub character_scene: Handle<Scene>,
     /// Animation clips extracted from the GLTF
     pub animations: PlayerAnimations,
+    /// Every named animation clip in the GLTF and the [`AnimationLibrary`], keyed by clip name
+    pub clips: HashMap<String, Handle<AnimationClip>>,
 }
 
 /// Animation clips extracted from the player GLTF
@@ -43,18 +49,103 @@ pub struct PlayerAnimations {
 
 }
 
+/// A logical clip slot, assigned a clip in the blending config's `AnimationAssignments`
+struct ClipSlot {
+    /// Name of the slot in the assignments
+    slot: &'static str,
+    /// Clip name the state machine and blend spaces play the slot's clip under
+    alias: &'static str,
+    /// Clip names tried in order when no clip is assigned, or the assigned one is missing
+    fallbacks: &'static [&'static str],
+}
+
+/// Slots every player needs, Mixamo exports name the clips like the fallbacks
+const CLIP_SLOTS: [ClipSlot; 5] = [
+    ClipSlot {
+        slot: "idle",
+        alias: "idle",
+        fallbacks: &["idle", "Idle"],
+    },
+    ClipSlot {
+        slot: "walk",
+        alias: "walk",
+        fallbacks: &["walk", "Walk", "walking", "Walking"],
+    },
+    ClipSlot {
+        slot: "run",
+        alias: "running",
+        fallbacks: &["running", "Running"],
+    },
+    ClipSlot {
+        slot: "jump",
+        alias: "standing_jump",
+        fallbacks: &["standing_jump", "Standing Jump"],
+    },
+    // Not assignable in the editor yet
+    ClipSlot {
+        slot: "running_jump",
+        alias: "running_jump",
+        fallbacks: &["running_jump", "Running Jump"],
+    },
+];
+
+/// Slots the player can do without, states playing them can't be entered if they're missing
+const OPTIONAL_CLIP_SLOTS: [ClipSlot; 1] = [
+    // Not assignable in the editor yet
+    ClipSlot {
+        slot: "air_jump",
+        alias: "air_jump",
+        fallbacks: &["air_jump", "Front Flip", "Flip"],
+    },
+];
+
+/// Finds the clip of a slot, returning the clip names tried if there is none
+fn resolve_slot(
+    slot: &ClipSlot,
+    assigned: Option<&str>,
+    clips: &HashMap<String, Handle<AnimationClip>>,
+) -> Result<Handle<AnimationClip>, Vec<String>> {
+    if let Some(assigned) = assigned {
+        if let Some(clip) = clips.get(assigned) {
+            return Ok(clip.clone());
+        }
+        warn!(
+            "Clip `{assigned}` assigned to `{}` not found, trying the default names",
+            slot.slot
+        );
+    }
+    slot.fallbacks
+        .iter()
+        .find_map(|name| clips.get(*name).cloned())
+        .ok_or_else(|| {
+            assigned
+                .into_iter()
+                .chain(slot.fallbacks.iter().copied())
+                .map(str::to_string)
+                .collect()
+        })
+}
+
 /// Extracts scene and animations from the loaded player GLTF
-/// This system runs once the PlayerGltfAsset is loaded
+/// This system runs until the PlayerGltfAsset, the animation library and the blending config
+/// are loaded, and again after the blending config is hot-reloaded
 pub fn extract_player_assets(
     mut commands: Commands,
     gltf_asset: Res<PlayerGltfAsset>,
     gltf_assets: Res<Assets<Gltf>>,
+    animation_library: Option<Res<AnimationLibrary>>,
+    blend_config: Option<Res<AnimationBlendingConfig>>,
     player_assets: Option<Res<PlayerAssets>>,
+    mut reported: Local<bool>,
 ) {
     // Only run once - if PlayerAssets already exists, we're done
     if player_assets.is_some() {
         return;
     }
+    // Library clips are merged in and the config picks the clips, so wait for them as well
+    let (Some(animation_library), Some(blend_config)) = (animation_library, blend_config) else {
+        return;
+    };
 
     // Try to get the loaded GLTF
     let Some(gltf) = gltf_assets.get(&gltf_asset.gltf) else {
@@ -64,54 +155,133 @@ pub fn extract_player_assets(
 
     // Extract the character scene (first scene in the GLTF)
     let Some(character_scene) = gltf.scenes.first().cloned() else {
-        error!("Player GLTF does not contain any scenes!");
+        if !*reported {
+            error!("Player GLTF does not contain any scenes!");
+            *reported = true;
+        }
         return;
     };
 
-    info!("Found {} animations in player GLTF", gltf.animations.len());
+    // Library clips replace GLTF clips of the same name
+    let mut clips: HashMap<String, Handle<AnimationClip>> = gltf
+        .named_animations
+        .iter()
+        .map(|(name, clip)| (name.to_string(), clip.clone()))
+        .collect();
+    clips.extend(animation_library.clips(&gltf_assets));
 
-    // Debug: Print animation names to help with mapping
-    for (idx, anim) in gltf.named_animations.iter().enumerate() {
-        info!("Animation {}: {}", idx, anim.0);
+    // Resolve every slot from the config's assignments, falling back to the default names
+    let mut resolved = HashMap::new();
+    let mut missing = Vec::new();
+    for slot in &CLIP_SLOTS {
+        match resolve_slot(slot, blend_config.animations.get(slot.slot), &clips) {
+            Ok(clip) => {
+                resolved.insert(slot.slot, clip);
+            }
+            Err(tried) => missing.push(format!("{} (tried {tried:?})", slot.slot)),
+        }
     }
-
-    // Extract animations by name from the GLTF
-    // Mixamo animation names: idle, running, standing_jump, running_jump
-    let idle = gltf.named_animations.get("idle")
-        .or_else(|| gltf.named_animations.get("Idle"))
-        .cloned();
-    let running = gltf.named_animations.get("running")
-        .or_else(|| gltf.named_animations.get("Running"))
-        .cloned();
-    let standing_jump = gltf.named_animations.get("standing_jump")
-        .or_else(|| gltf.named_animations.get("Standing Jump"))
-        .cloned();
-    let running_jump = gltf.named_animations.get("running_jump")
-        .or_else(|| gltf.named_animations.get("Running Jump"))
-        .cloned();
-    let walking = gltf.named_animations.get("walk").cloned();
-    // Verify we got all required animations
-    let (Some(idle), Some(running), Some(standing_jump), Some(running_jump), Some(walking)) =
-        (idle, running, standing_jump, running_jump, walking)
-    else {
-        error!("Failed to extract all required animations from player GLTF!");
-        error!("Expected animation names: idle, running, standing_jump, running_jump");
-        error!("Available animations: {:?}", gltf.named_animations.keys().collect::<Vec<_>>());
+    if !missing.is_empty() {
+        // Only report once, the system keeps running until the assets exist
+        if !*reported {
+            let mut available: Vec<_> = clips.keys().collect();
+            available.sort();
+            error!(
+                "Missing player animation slots: {}. Available clips: {available:?}",
+                missing.join(", ")
+            );
+            *reported = true;
+        }
         return;
-    };
+    }
+
+    // States and blend spaces refer to the slots by their alias, so they play the assigned clips
+    for slot in &CLIP_SLOTS {
+        clips.insert(slot.alias.to_string(), resolved[slot.slot].clone());
+    }
+    for slot in &OPTIONAL_CLIP_SLOTS {
+        match resolve_slot(slot, blend_config.animations.get(slot.slot), &clips) {
+            Ok(clip) => {
+                clips.insert(slot.alias.to_string(), clip);
+            }
+            Err(tried) => info!("No clip for optional slot {} (tried {tried:?})", slot.slot),
+        }
+    }
 
     // Create PlayerAssets resource with extracted data
     let assets = PlayerAssets {
         character_scene,
         animations: PlayerAnimations {
-            idle,
-            running,
-            standing_jump,
-            running_jump,
-            walking,
+            idle: resolved["idle"].clone(),
+            running: resolved["run"].clone(),
+            standing_jump: resolved["jump"].clone(),
+            running_jump: resolved["running_jump"].clone(),
+            walking: resolved["walk"].clone(),
         },
+        clips,
     };
 
+    info!(
+        "Extracted player scene and {} animation clips",
+        assets.clips.len()
+    );
     commands.insert_resource(assets);
-    info!("Successfully extracted player scene and animations from unified GLTF!");
+    // A hot-reloaded config may break the slots again
+    *reported = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use bevy::asset::uuid::Uuid;
+
+    use super::*;
+
+    const SLOT: ClipSlot = ClipSlot {
+        slot: "idle",
+        alias: "idle",
+        fallbacks: &["idle", "Idle"],
+    };
+
+    fn handle(id: u128) -> Handle<AnimationClip> {
+        Handle::Uuid(Uuid::from_u128(id), PhantomData)
+    }
+
+    fn clips(names: &[(&str, u128)]) -> HashMap<String, Handle<AnimationClip>> {
+        names
+            .iter()
+            .map(|(name, id)| (name.to_string(), handle(*id)))
+            .collect()
+    }
+
+    #[test]
+    fn assigned_clip_wins_over_fallbacks() {
+        let clips = clips(&[("Idle", 1), ("Breathing Idle", 2)]);
+        assert_eq!(
+            resolve_slot(&SLOT, Some("Breathing Idle"), &clips),
+            Ok(handle(2))
+        );
+    }
+
+    #[test]
+    fn fallbacks_are_tried_in_order() {
+        let clips = clips(&[("Idle", 1), ("idle", 2)]);
+        assert_eq!(resolve_slot(&SLOT, None, &clips), Ok(handle(2)));
+        // A missing assignment falls back to the default names
+        assert_eq!(resolve_slot(&SLOT, Some("Missing"), &clips), Ok(handle(2)));
+    }
+
+    #[test]
+    fn missing_clip_reports_the_names_tried() {
+        let clips = clips(&[("Walking", 1)]);
+        assert_eq!(
+            resolve_slot(&SLOT, Some("Missing"), &clips),
+            Err(vec![
+                "Missing".to_string(),
+                "idle".to_string(),
+                "Idle".to_string()
+            ])
+        );
+    }
 }
@@ -0,0 +1,134 @@
+//! Dodging with a quick dash, a roll on the ground and an air dash in the air.
+
+use std::ops::Range;
+
+use bevy::prelude::*;
+use bevy_tnua::prelude::*;
+
+use crate::screens::Screen;
+
+/// Name of the dash action, so the state machine can pick the roll or the air dash
+pub const DODGE: &str = "dodge";
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        update_dodge
+            .after(TnuaUserControlsSystems)
+            .run_if(in_state(Screen::Gameplay)),
+    );
+}
+
+/// Dash action settings and the cooldown between dashes
+#[derive(Component, Debug, Clone)]
+pub struct Dodge {
+    /// How far a dodge carries the character
+    pub distance: f32,
+    /// Speed during the dodge
+    pub speed: f32,
+    /// Seconds from the start of a dodge until the next one
+    pub cooldown: f32,
+    /// Whether the character can dash while airborne
+    pub allow_in_air: bool,
+    /// Seconds into the dodge during which the character can't be hit, if any
+    pub invulnerability: Option<Range<f32>>,
+    cooldown_remaining: f32,
+    /// Seconds since the ongoing dodge started
+    elapsed: Option<f32>,
+}
+
+impl Default for Dodge {
+    fn default() -> Self {
+        Self {
+            distance: 6.0,
+            speed: 30.0,
+            cooldown: 0.8,
+            allow_in_air: true,
+            invulnerability: Some(0.05..0.35),
+            cooldown_remaining: 0.0,
+            elapsed: None,
+        }
+    }
+}
+
+impl Dodge {
+    /// Whether the cooldown of the last dodge is over
+    pub fn is_ready(&self) -> bool {
+        self.cooldown_remaining <= 0.0
+    }
+
+    /// Whether the ongoing dodge is in its invulnerability window
+    pub fn is_invulnerable(&self) -> bool {
+        self.elapsed.is_some_and(|elapsed| {
+            self.invulnerability
+                .as_ref()
+                .is_some_and(|window| window.contains(&elapsed))
+        })
+    }
+
+    /// Advances the cooldown and the ongoing dodge by `delta` seconds
+    fn tick(&mut self, dodging: bool, delta: f32) {
+        self.cooldown_remaining = (self.cooldown_remaining - delta).max(0.0);
+
+        if !dodging {
+            self.elapsed = None;
+            return;
+        }
+        self.elapsed = Some(match self.elapsed {
+            Some(elapsed) => elapsed + delta,
+            None => {
+                self.cooldown_remaining = self.cooldown;
+                0.0
+            }
+        });
+    }
+}
+
+/// Starts the cooldown once a dash action actually starts, and times the ongoing dodge
+fn update_dodge(mut characters: Query<(&mut Dodge, &TnuaController)>, time: Res<Time>) {
+    let delta = time.delta_secs();
+    for (mut dodge, controller) in &mut characters {
+        dodge.tick(controller.action_name() == Some(DODGE), delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cooldown_starts_with_the_dodge() {
+        let mut dodge = Dodge::default();
+        assert!(dodge.is_ready());
+
+        dodge.tick(true, 0.1);
+        assert!(!dodge.is_ready());
+        // Holding the dodge doesn't restart the cooldown
+        dodge.tick(true, 0.4);
+        dodge.tick(false, 0.2);
+        assert!(!dodge.is_ready());
+        dodge.tick(false, 0.3);
+        assert!(dodge.is_ready());
+    }
+
+    #[test]
+    fn invulnerable_within_the_window() {
+        let mut dodge = Dodge::default();
+        assert!(!dodge.is_invulnerable());
+
+        // The first tick of the dodge is its start
+        dodge.tick(true, 0.1);
+        assert!(!dodge.is_invulnerable());
+        dodge.tick(true, 0.1);
+        assert!(dodge.is_invulnerable());
+        dodge.tick(true, 0.3);
+        assert!(!dodge.is_invulnerable());
+
+        dodge.tick(false, 0.1);
+        dodge.tick(true, 0.1);
+        assert!(!dodge.is_invulnerable());
+        dodge.invulnerability = None;
+        dodge.tick(true, 0.1);
+        assert!(!dodge.is_invulnerable());
+    }
+}
@@ -1,20 +1,47 @@
 mod assets;
+mod dodge;
+mod parkour;
+mod stamina;
 use crate::{
     asset_tracking::LoadResource,
-    game::{animations::models::AnimationState, third_person_camera::ThirdPersonCameraTarget},
+    game::{
+        animations::{
+            foot_ik::FootIk, layers::AnimationLayers, lean::ProceduralLean, look_at::LookAt,
+            models::AnimationState, motion_warping::MotionWarp, retargeting::Retargeting,
+            root_motion::RootMotion,
+        },
+        third_person_camera::ThirdPersonCameraTarget,
+    },
     screens::Screen,
 };
 use avian3d::prelude::*;
 use bevy::prelude::*;
 
 pub use assets::{PlayerAnimations, PlayerAssets, PlayerGltfAsset};
-use bevy_tnua::{TnuaAnimatingState, prelude::*};
+pub use dodge::{DODGE, Dodge};
+pub use parkour::{Parkour, ParkourAction, ParkourMove};
+pub use stamina::Stamina;
+use bevy_tnua::{
+    builtins::TnuaBuiltinCrouch,
+    control_helpers::{TnuaCrouchEnforcer, TnuaSimpleAirActionsCounter},
+    prelude::*,
+};
 use bevy_tnua_avian3d::*;
 
 // Player marker component
 #[derive(Component)]
 pub struct Player;
 
+/// How fast the player moves, picked from the input every frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Gait {
+    Walk,
+    #[default]
+    Run,
+    /// Running at `sprint_multiplier` times the run speed, draining [`Stamina`]
+    Sprint,
+}
+
 // Movement state
 #[derive(Component)]
 pub struct MovementController {
@@ -22,21 +49,59 @@ pub struct MovementController {
     pub run_speed: f32,
     pub sprint_multiplier: f32,
     pub jump_velocity: f32,
+    /// Speed while crouched
+    pub crouch_speed: f32,
     pub jump_height: f32,
+    /// Jumps the player can make before landing again, besides the one off the ground
+    pub air_jumps: usize,
+    /// Height of each air jump
+    pub air_jump_height: f32,
+    /// How much an air jump turns the horizontal velocity toward the input direction, from 0.0
+    /// (keeps going the same way) to 1.0 (goes exactly where the input points)
+    pub air_jump_redirect: f32,
+    /// Whether an air jump is left, always true on the ground
     pub double_jump_available: bool,
+    /// Whether the jump button was held in the last update, to tell fresh presses apart
+    pub jump_held: bool,
+    /// Whether the dodge button was held in the last update, to tell fresh presses apart
+    pub dodge_held: bool,
     pub is_grounded: bool,
+    /// Whether the player is crouched, with the shorter collider
+    pub is_crouching: bool,
+    /// Gait the player moves at
+    pub gait: Gait,
+    /// Whether the player is aiming, facing the camera instead of the move direction
+    pub is_aiming: bool,
+    /// Whether the current animation (e.g. a hard landing) ignores movement input
+    pub movement_locked: bool,
+    /// Normalized direction the player wants to move in, zero without input or while aiming
+    pub input_direction: Vec3,
+    /// Facing set by the current animation (e.g. turning in place), overriding the input
+    pub animated_forward: Option<Dir3>,
 }
 
 impl Default for MovementController {
     fn default() -> Self {
         Self {
-            walk_speed: 2.0,  // Walking speed (default movement)
-            run_speed: 8.0,   // Running speed (when Shift is held)
-            sprint_multiplier: 1.5,
+            walk_speed: 2.0,  // Walking speed (when Alt is held)
+            run_speed: 8.0,   // Running speed (default movement)
+            sprint_multiplier: 1.5, // Sprinting speed over running (when Shift is held)
+            crouch_speed: 1.5,
             jump_velocity: 22.0, // Increased from 8.0 for more responsive jumping
             jump_height: 4.0, // Increased from 8.0 for more responsive jumping
-            double_jump_available: false,
+            air_jumps: 1,
+            air_jump_height: 3.0,
+            air_jump_redirect: 0.8,
+            double_jump_available: true,
+            jump_held: false,
+            dodge_held: false,
             is_grounded: false,
+            is_crouching: false,
+            gait: Gait::default(),
+            is_aiming: false,
+            movement_locked: false,
+            input_direction: Vec3::ZERO,
+            animated_forward: None,
         }
     }
 }
@@ -44,15 +109,30 @@ impl Default for MovementController {
 // Constants
 pub const PLAYER_HEIGHT: f32 = 1.1;
 pub const PLAYER_RADIUS: f32 = 0.5;
+/// How much lower the player floats while crouched, so the crouched collider clears the ground
+pub const CROUCH_FLOAT_OFFSET: f32 = -0.3;
 
 // Player spawn command
 pub struct SpawnPlayer {
     pub position: Vec3,
+    /// Model to spawn instead of the one in the player GLTF
+    pub model: Option<CharacterModel>,
+}
+
+/// A character model whose rig differs from the one the player clips were authored on, see
+/// [`Retargeting`]
+pub struct CharacterModel {
+    /// GLTF file below `assets`, its first scene is spawned
+    pub gltf: &'static str,
+    /// Bone map below `assets` from the clip rig to the model's rig
+    pub bone_map: &'static str,
 }
 
 impl Command for SpawnPlayer {
     fn apply(self, world: &mut World) {
-        let _ = world.run_system_cached_with(spawn_player, self);
+        if let Err(error) = world.run_system_cached_with(spawn_player, self) {
+            error!("Failed to spawn the player: {error}");
+        }
     }
 }
 
@@ -60,8 +140,13 @@ fn spawn_player(
     In(spawn_config): In<SpawnPlayer>,
     mut commands: Commands,
     player_assets: Res<PlayerAssets>,
+    asset_server: Res<AssetServer>,
 ) {
-    commands
+    let scene = match &spawn_config.model {
+        Some(model) => asset_server.load(GltfAssetLabel::Scene(0).from_asset(model.gltf)),
+        None => player_assets.character_scene.clone(),
+    };
+    let player = commands
         .spawn((
             Name::new("Player"),
             Player,
@@ -73,18 +158,52 @@ fn spawn_player(
             // Avian3D physics components
             RigidBody::Dynamic,
             Collider::capsule(PLAYER_HEIGHT / 2., PLAYER_RADIUS),
-            TnuaController::default(),
             LockedAxes::ROTATION_LOCKED.unlock_rotation_y(), // Prevent player from tipping over
-            TnuaAvian3dSensorShape(Collider::cylinder(PLAYER_HEIGHT / 2., 0.0)),
-            TnuaAnimatingState::<AnimationState>::default(),
+            // Tnua character controller
+            (
+                TnuaController::default(),
+                TnuaSimpleAirActionsCounter::default(),
+                TnuaAvian3dSensorShape(Collider::cylinder(PLAYER_HEIGHT / 2., 0.0)),
+                // Keeps the player crouched under obstacles, casting up from the top of the
+                // standing collider as far as crouching lowers it
+                TnuaCrouchEnforcer::new(
+                    Vec3::Y * (PLAYER_HEIGHT / 2. + PLAYER_RADIUS / 2.),
+                    |cmd| {
+                        cmd.insert(TnuaAvian3dSensorShape(Collider::cylinder(
+                            PLAYER_HEIGHT / 2.,
+                            0.0,
+                        )));
+                    },
+                ),
+            ),
+            // Animation
+            (
+                AnimationState::default(),
+                FootIk::default(),
+                RootMotion::default(),
+                AnimationLayers::default(),
+                ProceduralLean::default(),
+                LookAt::default(),
+                MotionWarp::default(),
+            ),
+            Stamina::default(),
+            Dodge::default(),
+            Parkour::default(),
         ))
         .with_children(|parent| {
             parent.spawn((
-                SceneRoot(player_assets.character_scene.clone()),
+                SceneRoot(scene),
                 Transform::from_translation(Vec3::new(0., -0.8, 0.))
                     .with_rotation(Quat::from_rotation_y(std::f32::consts::PI))
             ));
+        })
+        .id();
+
+    if let Some(model) = spawn_config.model {
+        commands.entity(player).insert(Retargeting {
+            bone_map: asset_server.load(model.bone_map),
         });
+    }
 }
 
 pub(super) fn plugin(app: &mut App) {
@@ -95,9 +214,39 @@ pub(super) fn plugin(app: &mut App) {
     app.add_systems(
         Update,
         assets::extract_player_assets
-            .run_if(resource_added::<PlayerGltfAsset>)
+            .run_if(resource_exists::<PlayerGltfAsset>.and(not(resource_exists::<PlayerAssets>)))
+    );
+
+    app.add_plugins((stamina::plugin, dodge::plugin, parkour::plugin));
+
+    app.add_systems(
+        FixedUpdate,
+        resize_crouch_collider
+            .after(TnuaUserControlsSystems)
+            .run_if(in_state(Screen::Gameplay)),
     );
 
     // Set stronger gravity for faster falling (default is -9.81)
     app.insert_resource(Gravity(Vec3::new(0.0, -100.0, 0.0)));
 }
+
+/// Shrinks the player's collider while a crouch action is active
+///
+/// The standing collider only comes back once the crouch action has finished rising, so it
+/// never reaches into the ground.
+fn resize_crouch_collider(
+    mut players: Query<(&TnuaController, &mut MovementController, &mut Collider), With<Player>>,
+) {
+    for (controller, mut movement, mut collider) in &mut players {
+        let crouching = controller.concrete_action::<TnuaBuiltinCrouch>().is_some();
+        if crouching == movement.is_crouching {
+            continue;
+        }
+        movement.is_crouching = crouching;
+        *collider = if crouching {
+            Collider::capsule(PLAYER_HEIGHT / 2., 0.0)
+        } else {
+            Collider::capsule(PLAYER_HEIGHT / 2., PLAYER_RADIUS)
+        };
+    }
+}
@@ -0,0 +1,559 @@
+//! Looking ahead of the player for obstacles to vault, climb or jump over.
+//!
+//! Every update the sensor casts ahead of the player and sorts what it finds into a few kinds of
+//! obstacle. Pressing Space on the ground then starts the parkour action for the obstacle ahead,
+//! see `apply_controls`: a vault or climb warped onto the obstacle's top, a jump up to hang from
+//! its top edge, or a jump shaped to get across a gap.
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::prelude::*;
+
+use crate::{
+    game::configs::{AnimationBlendingConfig, ClimbConfig, VaultConfig},
+    screens::Screen,
+};
+
+use super::{MovementController, PLAYER_RADIUS};
+
+/// Radius of the sphere cast ahead for walls
+const WALL_PROBE_RADIUS: f32 = 0.2;
+/// How far down from the player's center the ground is looked for
+const GROUND_PROBE_DISTANCE: f32 = 1.5;
+/// Spacing of the downward rays looking for an edge and the ground across a gap
+const EDGE_PROBE_STEP: f32 = 0.25;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        detect_obstacles
+            .before(TnuaUserControlsSystems)
+            .run_if(in_state(Screen::Gameplay)),
+    );
+}
+
+/// What kind of obstacle is ahead of the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObstacleKind {
+    /// About waist-high, low enough to vault over
+    LowWall,
+    /// About shoulder-high, climbed over with the hands on top
+    MidWall,
+    /// Above the head, the top can only be reached by jumping up to hang from it
+    TallWall,
+    /// A drop with ground across it in jumping range
+    Gap,
+    /// A drop without anything to land on across it
+    Ledge,
+}
+
+impl ObstacleKind {
+    /// The parkour action for the obstacle, ledges are jumped off like anywhere else
+    pub fn action(self) -> Option<ParkourAction> {
+        match self {
+            Self::LowWall => Some(ParkourAction::Vault),
+            Self::MidWall => Some(ParkourAction::ClimbOver),
+            Self::TallWall => Some(ParkourAction::JumpToHang),
+            Self::Gap => Some(ParkourAction::LongJump),
+            Self::Ledge => None,
+        }
+    }
+}
+
+/// Action getting the player over an obstacle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParkourAction {
+    Vault,
+    ClimbOver,
+    JumpToHang,
+    LongJump,
+    /// Holding on to the top edge after jumping up to it, until climbing up or letting go
+    Hang,
+    /// Climbing from the hang onto the top
+    ClimbUp,
+}
+
+impl ParkourAction {
+    /// Name of the Tnua action, which the state machine picks the clip by
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Vault => "vault",
+            Self::ClimbOver => "climb_over",
+            Self::JumpToHang => "jump_to_hang",
+            Self::LongJump => "long_jump",
+            Self::Hang => "hang",
+            Self::ClimbUp => "climb_up",
+        }
+    }
+
+    /// Whether the action carries the character along a warped path instead of jumping
+    pub fn is_warped(self) -> bool {
+        matches!(
+            self,
+            Self::Vault | Self::ClimbOver | Self::JumpToHang | Self::ClimbUp
+        )
+    }
+}
+
+/// Obstacle found ahead of the player
+#[derive(Debug, Clone, Copy)]
+pub struct Obstacle {
+    pub kind: ObstacleKind,
+    /// Horizontal direction from the player across the obstacle
+    pub direction: Dir3,
+    /// Horizontal distance from the player's center to the wall, or to the edge of the drop
+    pub distance: f32,
+    /// Top edge of the wall above the point the sensor hit, or the edge of the drop
+    pub edge: Vec3,
+    /// Height of the wall above the ground, or how far the ground drops
+    pub height: f32,
+    /// Thickness of the wall up to the sensor's reach, or width of the gap
+    pub length: f32,
+    /// Ground to land on past the obstacle, the top for walls thicker than the reach
+    pub landing: Option<Vec3>,
+}
+
+/// Parkour action started for an obstacle, with the jump that gets over it unless it's warped
+#[derive(Debug, Clone, Copy)]
+pub struct ParkourMove {
+    pub action: ParkourAction,
+    pub obstacle: Obstacle,
+    /// Height of the jump, from the ground to the highest point of the feet
+    pub jump_height: f32,
+    /// Horizontal velocity to launch at, the least that gets across
+    pub velocity: Vec3,
+}
+
+impl ParkourMove {
+    /// Points of the warped path of the move for a character whose center is at `start`, and
+    /// `center_height` above its feet when standing
+    ///
+    /// `None` for moves that aren't warped, and for vaults without ground to land on.
+    pub fn warp_path(
+        &self,
+        start: Vec3,
+        center_height: f32,
+        config: &AnimationBlendingConfig,
+    ) -> Option<Vec<(f32, Vec3)>> {
+        let climb = &config.climb;
+        match self.action {
+            ParkourAction::Vault => self.vault_path(start, &config.vault).map(Vec::from),
+            ParkourAction::ClimbOver => {
+                let grab = (climb.grab_time, self.grab_point(start, climb));
+                let climb_up = self.climb_up_points(grab.1, center_height, climb);
+                Some(
+                    [(0.0, start), grab]
+                        .into_iter()
+                        .chain(climb_up.map(|(time, point)| (climb.grab_time + time, point)))
+                        .collect(),
+                )
+            }
+            ParkourAction::JumpToHang => Some(vec![
+                (0.0, start),
+                (climb.grab_time, self.grab_point(start, climb)),
+            ]),
+            ParkourAction::ClimbUp => Some(
+                std::iter::once((0.0, start))
+                    .chain(self.climb_up_points(start, center_height, climb))
+                    .collect(),
+            ),
+            ParkourAction::LongJump | ParkourAction::Hang => None,
+        }
+    }
+
+    /// Velocity the character is left with at the end of the warped path, vaults keep running
+    /// while climbs end standing on the top
+    pub fn exit_velocity(&self) -> Vec3 {
+        match self.action {
+            ParkourAction::Vault => self.velocity,
+            _ => Vec3::ZERO,
+        }
+    }
+
+    /// Where the center hangs with the hands on the top edge, no lower than `start`
+    fn grab_point(&self, start: Vec3, config: &ClimbConfig) -> Vec3 {
+        let obstacle = &self.obstacle;
+        let hang =
+            obstacle.edge - obstacle.direction * config.hang_offset - Vec3::Y * config.hang_height;
+        hang.with_y(hang.y.max(start.y))
+    }
+
+    /// Points climbing from the hands on the edge at `grab` onto the top, timed from the grab
+    fn climb_up_points(
+        &self,
+        grab: Vec3,
+        center_height: f32,
+        config: &ClimbConfig,
+    ) -> [(f32, Vec3); 2] {
+        let obstacle = &self.obstacle;
+        let top = obstacle.edge
+            + obstacle.direction * config.top_forward.min(obstacle.length / 2.0)
+            + Vec3::Y * center_height;
+        // Up in front of the face first, so the path doesn't cut through the edge
+        [
+            (config.pull_up_time, grab.with_y(top.y)),
+            (config.climb_up_time, top),
+        ]
+    }
+
+    /// Points of the warped vault path for a character whose center is at `start`, see
+    /// [`MotionWarp::start`](crate::game::animations::motion_warping::MotionWarp::start)
+    ///
+    /// The hand lands on the obstacle's top edge at the clip's contact time, whatever the
+    /// distance the vault starts from. `None` without ground to land on.
+    pub fn vault_path(&self, start: Vec3, config: &VaultConfig) -> Option<[(f32, Vec3); 4]> {
+        let obstacle = &self.obstacle;
+        let direction = *obstacle.direction;
+        let center_height = start.y - (obstacle.edge.y - obstacle.height);
+        let contact =
+            obstacle.edge - direction * config.hand_forward - Vec3::Y * config.hand_height;
+        let over = obstacle.edge + direction * (obstacle.length / 2.0) + Vec3::Y * config.clearance;
+        let landing = obstacle.landing? + Vec3::Y * center_height;
+        Some([
+            (0.0, start),
+            (config.contact_time, contact),
+            ((config.contact_time + config.land_time) / 2.0, over),
+            (config.land_time, landing),
+        ])
+    }
+}
+
+/// Looks for obstacles ahead of the character and plans the parkour moves over them
+///
+/// Heights are measured from the ground under the character. Walls lower than `min_height`
+/// or taller than `tall_wall_max` aren't parkour obstacles.
+#[derive(Component, Debug, Clone)]
+pub struct Parkour {
+    /// How far ahead walls are looked for
+    pub reach: f32,
+    /// Walls below this are stepped or jumped over as usual
+    pub min_height: f32,
+    /// Highest wall that is vaulted
+    pub low_wall_max: f32,
+    /// Highest wall that is climbed over
+    pub mid_wall_max: f32,
+    /// Highest wall whose top can be jumped up to
+    pub tall_wall_max: f32,
+    /// How far ahead an edge is looked for
+    pub edge_reach: f32,
+    /// Lowest drop that counts as an edge
+    pub min_drop: f32,
+    /// Widest gap that is long jumped
+    pub max_gap: f32,
+    /// How much higher or lower the far side of a gap may be than the near side
+    pub gap_tolerance: f32,
+    /// Height of the long jump
+    pub long_jump_height: f32,
+    /// Room left between the feet and the top of a wall jumped over
+    pub clearance: f32,
+    /// How high above the feet the hands reach with the arms up
+    pub hang_reach: f32,
+    /// Obstacle found in the last update, if any
+    pub ahead: Option<Obstacle>,
+    /// Move the character is making, until its action ends
+    pub target: Option<ParkourMove>,
+}
+
+impl Default for Parkour {
+    fn default() -> Self {
+        Self {
+            reach: 2.0,
+            min_height: 0.5,
+            low_wall_max: 1.2,
+            mid_wall_max: 1.7,
+            tall_wall_max: 3.2,
+            edge_reach: 1.5,
+            min_drop: 0.75,
+            max_gap: 5.0,
+            gap_tolerance: 0.5,
+            long_jump_height: 1.5,
+            clearance: 0.3,
+            hang_reach: 2.0,
+            ahead: None,
+            target: None,
+        }
+    }
+}
+
+impl Parkour {
+    /// Plans the move over the obstacle ahead, `None` if there's nothing ahead to get over
+    ///
+    /// The jump speed assumes the character rises and falls at `gravity`.
+    pub fn plan(&self, gravity: f32) -> Option<ParkourMove> {
+        let obstacle = self.ahead?;
+        let action = obstacle.kind.action()?;
+        // Height of the jump and how far it has to carry by the top
+        let (jump_height, distance) = match action {
+            // Over the middle of the wall
+            ParkourAction::Vault | ParkourAction::ClimbOver => (
+                obstacle.height + self.clearance,
+                obstacle.distance + obstacle.length / 2.0,
+            ),
+            // At the wall
+            ParkourAction::JumpToHang => (
+                (obstacle.height - self.hang_reach + self.clearance).max(self.clearance),
+                (obstacle.distance - PLAYER_RADIUS).max(0.0),
+            ),
+            // Past the far edge when back down at the height of the jump, so halfway by the top
+            ParkourAction::LongJump => (
+                self.long_jump_height,
+                (obstacle.distance + obstacle.length + PLAYER_RADIUS) / 2.0,
+            ),
+            // Only ever follow a jump to hang
+            ParkourAction::Hang | ParkourAction::ClimbUp => return None,
+        };
+        let rise_time = (2.0 * jump_height / gravity).sqrt();
+        let speed = distance / rise_time;
+        Some(ParkourMove {
+            action,
+            obstacle,
+            jump_height,
+            velocity: obstacle.direction * speed,
+        })
+    }
+
+    /// Finds a wall ahead between `min_height` and `tall_wall_max`
+    fn find_wall(
+        &self,
+        ground: Vec3,
+        direction: Dir3,
+        spatial_query: &SpatialQuery,
+        filter: &SpatialQueryFilter,
+    ) -> Option<Obstacle> {
+        let origin = ground + Vec3::Y * (self.min_height + WALL_PROBE_RADIUS);
+        let hit = spatial_query.cast_shape(
+            &Collider::sphere(WALL_PROBE_RADIUS),
+            origin,
+            Quat::IDENTITY,
+            direction,
+            &ShapeCastConfig::from_max_distance(self.reach),
+            filter,
+        )?;
+        // Slopes and steps are walked up
+        if hit.normal1.y.abs() > 0.3 {
+            return None;
+        }
+
+        // Down onto the top from just inside the face, starting inside a wall means it's too tall
+        let face = hit.point1 + direction * 0.05;
+        let top_probe = self.tall_wall_max + 0.5;
+        let top_origin = Vec3::new(face.x, ground.y + top_probe, face.z);
+        let top = spatial_query.cast_ray(top_origin, Dir3::NEG_Y, top_probe, true, filter)?;
+        if top.distance <= 0.0 {
+            return None;
+        }
+        let edge = top_origin - Vec3::Y * top.distance;
+        let height = edge.y - ground.y;
+        if !(self.min_height..=self.tall_wall_max).contains(&height) {
+            return None;
+        }
+        let kind = if height <= self.low_wall_max {
+            ObstacleKind::LowWall
+        } else if height <= self.mid_wall_max {
+            ObstacleKind::MidWall
+        } else {
+            ObstacleKind::TallWall
+        };
+
+        // Back along the top from beyond the reach to find the far face
+        let back_origin = edge + direction * self.reach - Vec3::Y * 0.05;
+        let length = spatial_query
+            .cast_ray(back_origin, -direction, self.reach, true, filter)
+            .map_or(self.reach, |hit| self.reach - hit.distance);
+
+        // Down past the far face, far enough for the character to stand clear of it
+        let landing_origin = edge + direction * (length + PLAYER_RADIUS) + Vec3::Y * 0.5;
+        let landing = spatial_query
+            .cast_ray(
+                landing_origin,
+                Dir3::NEG_Y,
+                0.5 + height + self.max_gap,
+                true,
+                filter,
+            )
+            .filter(|hit| hit.distance > 0.0)
+            .map(|hit| landing_origin - Vec3::Y * hit.distance);
+
+        Some(Obstacle {
+            kind,
+            direction,
+            distance: (hit.point1 - ground).reject_from(Vec3::Y).length(),
+            edge,
+            height,
+            length,
+            landing,
+        })
+    }
+
+    /// Finds a drop ahead, and the ground across it if the gap can be jumped
+    fn find_drop(
+        &self,
+        ground: Vec3,
+        direction: Dir3,
+        spatial_query: &SpatialQuery,
+        filter: &SpatialQueryFilter,
+    ) -> Option<Obstacle> {
+        // Drop of the ground at a distance ahead, `None` where there's a wall
+        let probe_height = 0.5;
+        let probe_depth = probe_height + self.max_gap;
+        let drop_at = |distance: f32| {
+            let origin = ground + direction * distance + Vec3::Y * probe_height;
+            match spatial_query.cast_ray(origin, Dir3::NEG_Y, probe_depth, true, filter) {
+                Some(hit) if hit.distance <= 0.0 => None,
+                Some(hit) => Some(hit.distance - probe_height),
+                None => Some(f32::INFINITY),
+            }
+        };
+
+        let steps = |from: f32, to: f32| {
+            (0..)
+                .map(move |step| from + step as f32 * EDGE_PROBE_STEP)
+                .take_while(move |distance| *distance <= to)
+        };
+        let (edge_distance, drop) = steps(EDGE_PROBE_STEP, self.edge_reach)
+            .map(|distance| (distance, drop_at(distance)))
+            .take_while(|(_, drop)| drop.is_some())
+            .find_map(|(distance, drop)| {
+                drop.filter(|drop| *drop >= self.min_drop)
+                    .map(|drop| (distance, drop))
+            })?;
+        // The edge lies between the last probe on the ground and the first past it
+        let edge_distance = edge_distance - EDGE_PROBE_STEP / 2.0;
+
+        let far_side = steps(
+            edge_distance + EDGE_PROBE_STEP,
+            edge_distance + self.max_gap,
+        )
+        .find_map(|distance| {
+            drop_at(distance)
+                .filter(|drop| drop.abs() <= self.gap_tolerance)
+                .map(|drop| (distance, drop))
+        });
+
+        let edge = ground + direction * edge_distance;
+        let (kind, length, landing) = match far_side {
+            Some((far_distance, far_drop)) => (
+                ObstacleKind::Gap,
+                far_distance - edge_distance,
+                Some(ground + direction * far_distance - Vec3::Y * far_drop),
+            ),
+            None => (
+                ObstacleKind::Ledge,
+                0.0,
+                drop.is_finite().then(|| edge - Vec3::Y * drop),
+            ),
+        };
+        Some(Obstacle {
+            kind,
+            direction,
+            distance: edge_distance,
+            edge,
+            height: drop.min(self.max_gap),
+            length,
+            landing,
+        })
+    }
+}
+
+/// Looks ahead of grounded characters, in the direction they move or face
+fn detect_obstacles(
+    mut characters: Query<(Entity, &mut Parkour, &MovementController, &Transform)>,
+    spatial_query: SpatialQuery,
+) {
+    for (entity, mut parkour, movement, transform) in &mut characters {
+        let filter = SpatialQueryFilter::from_excluded_entities([entity]);
+        let ground = spatial_query
+            .cast_ray(
+                transform.translation,
+                Dir3::NEG_Y,
+                GROUND_PROBE_DISTANCE,
+                true,
+                &filter,
+            )
+            .map(|hit| transform.translation - Vec3::Y * hit.distance);
+        let direction = Dir3::new(movement.input_direction)
+            .or_else(|_| Dir3::new(transform.forward().reject_from(Vec3::Y)))
+            .ok();
+
+        parkour.ahead = ground.zip(direction).and_then(|(ground, direction)| {
+            parkour
+                .find_wall(ground, direction, &spatial_query, &filter)
+                .or_else(|| parkour.find_drop(ground, direction, &spatial_query, &filter))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAVITY: f32 = 20.0;
+
+    fn parkour_ahead(kind: ObstacleKind, height: f32) -> Parkour {
+        Parkour {
+            ahead: Some(Obstacle {
+                kind,
+                direction: Dir3::X,
+                distance: 1.0,
+                edge: Vec3::new(1.0, height, 0.0),
+                height,
+                length: 3.0,
+                landing: None,
+            }),
+            ..default()
+        }
+    }
+
+    /// Horizontal distance the move covers by the top of its jump
+    fn distance_by_top(parkour_move: &ParkourMove) -> f32 {
+        parkour_move.velocity.length() * (2.0 * parkour_move.jump_height / GRAVITY).sqrt()
+    }
+
+    #[test]
+    fn nothing_to_plan_without_an_obstacle() {
+        assert!(Parkour::default().plan(GRAVITY).is_none());
+        let ledge = parkour_ahead(ObstacleKind::Ledge, 2.0);
+        assert!(ledge.plan(GRAVITY).is_none());
+    }
+
+    #[test]
+    fn walls_are_cleared_over_their_middle() {
+        let parkour = parkour_ahead(ObstacleKind::LowWall, 1.0);
+        let vault = parkour.plan(GRAVITY).unwrap();
+        assert_eq!(vault.action, ParkourAction::Vault);
+        assert!((vault.jump_height - 1.3).abs() < 1e-4);
+        assert!(vault.velocity.normalize().abs_diff_eq(Vec3::X, 1e-4));
+        assert!((distance_by_top(&vault) - 2.5).abs() < 1e-4);
+
+        let parkour = parkour_ahead(ObstacleKind::MidWall, 1.5);
+        let climb = parkour.plan(GRAVITY).unwrap();
+        assert_eq!(climb.action, ParkourAction::ClimbOver);
+        assert!((climb.jump_height - 1.8).abs() < 1e-4);
+    }
+
+    #[test]
+    fn jumps_to_hang_reach_the_wall() {
+        let parkour = parkour_ahead(ObstacleKind::TallWall, 3.0);
+        let jump = parkour.plan(GRAVITY).unwrap();
+        assert_eq!(jump.action, ParkourAction::JumpToHang);
+        // Hands up at 2m, so 1m up to the edge and the clearance
+        assert!((jump.jump_height - 1.3).abs() < 1e-4);
+        assert!((distance_by_top(&jump) - (1.0 - PLAYER_RADIUS)).abs() < 1e-4);
+
+        // The edge is in reach from the ground, still jump by the clearance
+        let parkour = parkour_ahead(ObstacleKind::TallWall, 1.9);
+        let jump = parkour.plan(GRAVITY).unwrap();
+        assert!((jump.jump_height - parkour.clearance).abs() < 1e-4);
+    }
+
+    #[test]
+    fn long_jumps_land_past_the_gap() {
+        let parkour = parkour_ahead(ObstacleKind::Gap, 2.0);
+        let jump = parkour.plan(GRAVITY).unwrap();
+        assert_eq!(jump.action, ParkourAction::LongJump);
+        assert_eq!(jump.jump_height, parkour.long_jump_height);
+        // Back down at the jump's height twice as far as by the top
+        let far_side = 1.0 + 3.0 + PLAYER_RADIUS;
+        assert!((2.0 * distance_by_top(&jump) - far_side).abs() < 1e-4);
+    }
+}
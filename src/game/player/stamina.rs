@@ -0,0 +1,161 @@
+//! Stamina spent by sprinting.
+
+use bevy::prelude::*;
+use bevy_tnua::prelude::*;
+
+use crate::{game::animations::layers::AnimationLayers, screens::Screen};
+
+use super::{Gait, MovementController};
+
+/// Animation layer that plays while the character is out of breath
+const TIRED_LAYER: &str = "tired";
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        update_stamina
+            .after(TnuaUserControlsSystems)
+            .run_if(in_state(Screen::Gameplay)),
+    );
+}
+
+/// Stamina drained by sprinting and regained otherwise
+///
+/// Running out blocks sprinting until `recovery` of the stamina is back.
+#[derive(Component, Debug, Clone)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    /// Stamina spent per second of sprinting
+    pub drain_rate: f32,
+    /// Stamina regained per second when not sprinting
+    pub regen_rate: f32,
+    /// Seconds after sprinting before stamina is regained
+    pub regen_delay: f32,
+    /// Share of `max` needed to sprint again after running out
+    pub recovery: f32,
+    /// Share of `max` below which the character breathes heavily
+    pub tired_below: f32,
+    exhausted: bool,
+    rested: f32,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Self {
+            current: 100.0,
+            max: 100.0,
+            drain_rate: 20.0,
+            regen_rate: 15.0,
+            regen_delay: 1.0,
+            recovery: 0.3,
+            tired_below: 0.25,
+            exhausted: false,
+            rested: 0.0,
+        }
+    }
+}
+
+impl Stamina {
+    /// Whether there is stamina to sprint with
+    pub fn can_sprint(&self) -> bool {
+        !self.exhausted && self.current > 0.0
+    }
+
+    /// Whether the character has run out of stamina or is about to
+    pub fn is_tired(&self) -> bool {
+        self.exhausted || self.current < self.max * self.tired_below
+    }
+
+    /// Advances the stamina by `delta` seconds of sprinting or not
+    fn tick(&mut self, sprinting: bool, delta: f32) {
+        if sprinting {
+            self.current = (self.current - self.drain_rate * delta).max(0.0);
+            self.rested = 0.0;
+            if self.current <= 0.0 {
+                self.exhausted = true;
+            }
+        } else {
+            self.rested += delta;
+            if self.rested >= self.regen_delay {
+                self.current = (self.current + self.regen_rate * delta).min(self.max);
+            }
+            if self.current >= self.max * self.recovery {
+                self.exhausted = false;
+            }
+        }
+    }
+}
+
+/// Drains stamina while sprinting, regains it after a pause, and plays the tired layer while
+/// stamina is low
+fn update_stamina(
+    mut characters: Query<(
+        &mut Stamina,
+        &MovementController,
+        Option<&mut AnimationLayers>,
+    )>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+    for (mut stamina, movement, layers) in &mut characters {
+        stamina.tick(movement.gait == Gait::Sprint, delta);
+
+        if let Some(mut layers) = layers {
+            layers.set_active(TIRED_LAYER, stamina.is_tired());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ticks the stamina at 10 steps per second for `seconds`
+    fn run(stamina: &mut Stamina, sprinting: bool, seconds: f32) {
+        for _ in 0..(seconds * 10.0).round() as usize {
+            stamina.tick(sprinting, 0.1);
+        }
+    }
+
+    #[test]
+    fn sprinting_drains_until_exhausted() {
+        let mut stamina = Stamina::default();
+        run(&mut stamina, true, 2.0);
+        assert!((stamina.current - 60.0).abs() < 1e-3);
+        assert!(stamina.can_sprint());
+
+        run(&mut stamina, true, 3.5);
+        assert_eq!(stamina.current, 0.0);
+        assert!(!stamina.can_sprint());
+        assert!(stamina.is_tired());
+    }
+
+    #[test]
+    fn regains_after_the_delay() {
+        let mut stamina = Stamina::default();
+        run(&mut stamina, true, 1.0);
+        let drained = stamina.current;
+
+        run(&mut stamina, false, 0.9);
+        assert_eq!(stamina.current, drained);
+        run(&mut stamina, false, 1.0);
+        assert!(stamina.current > drained);
+        run(&mut stamina, false, 10.0);
+        assert_eq!(stamina.current, stamina.max);
+    }
+
+    #[test]
+    fn exhaustion_lasts_until_recovered() {
+        let mut stamina = Stamina::default();
+        run(&mut stamina, true, 6.0);
+        assert!(!stamina.can_sprint());
+
+        // 1s delay, then 15 per second up to 30% of 100
+        run(&mut stamina, false, 2.5);
+        assert!(!stamina.can_sprint());
+        run(&mut stamina, false, 1.0);
+        assert!(stamina.can_sprint());
+        assert!(!stamina.is_tired());
+    }
+}
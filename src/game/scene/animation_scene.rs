@@ -330,21 +330,4 @@ fn spawn_ramp(
         "Spawned ramp: {} at {} with angle {}°",
         label, position, angle_degrees
     );
-    let animation_files = vec![
-        "animation_models/Jump To Hang.glb",
-        "animation_models/Freehang Climb.glb",
-        "animation_models/Standard Run.glb",
-        "animation_models/Jump To Freehang.glb",
-        "animation_models/Running Slide.glb",
-        "animation_models/Over Obstacle Jumping.glb",
-        "animation_models/Braced Hang To Crouch.glb",
-        "animation_models/Braced Hang Drop.glb",
-        "animation_models/Breathing Idle.glb",
-        "animation_models/Standing Jumping.glb",
-        "animation_models/Braced Hang.glb",
-        "animation_models/Hard Landing.glb",
-        "animation_models/Free Hang To Braced.glb",
-        "animation_models/Falling To Roll.glb",
-        "animation_models/Stand To Freehang.glb",
-    ];
 }
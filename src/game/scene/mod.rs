@@ -19,6 +19,8 @@ pub fn spawn_level(world: &mut World) {
     // but add things like walls etc. here.
     SpawnPlayer {
         position: Vec3::new(0., 5., 0.),
+        // Models with another rig play the player clips through a bone map, see `CharacterModel`
+        model: None,
     }
     .apply(world);
 }
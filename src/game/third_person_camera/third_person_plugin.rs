@@ -148,6 +148,15 @@ impl Default for ThirdPersonCamera {
     }
 }
 
+impl ThirdPersonCamera {
+    /// Returns true if aiming is enabled and the aim button is held on the mouse or gamepad
+    pub fn is_aiming(&self, mouse: &ButtonInput<MouseButton>, gamepad: Option<&Gamepad>) -> bool {
+        let is_gamepad_aiming =
+            gamepad.is_some_and(|gp| gp.pressed(self.gamepad_settings.aim_button));
+        self.aim_enabled && (mouse.pressed(self.aim_button) || is_gamepad_aiming)
+    }
+}
+
 /// Sets the zoom bounds (min & max)
 pub struct Zoom {
     pub min: f32,
@@ -319,15 +328,8 @@ fn aim(
         Err(_) => None,
     };
 
-    let is_gamepad_aiming = match gamepad {
-        Some(gp) => gp.pressed(cam.gamepad_settings.aim_button),
-        None => false,
-    };
-
     // check if aim button was pressed
-    let is_mouse_aiming = mouse.pressed(cam.aim_button);
-
-    if is_mouse_aiming || is_gamepad_aiming {
+    if cam.is_aiming(&mouse, gamepad) {
         // rotate player or target to face direction he is aiming
         player_transform.look_to(*cam_transform.forward(), Vec3::Y);
 
@@ -1,6 +1,6 @@
 //! Animation Editor screen for creating and editing animation blend configurations.
 
-use std::{fs, path::PathBuf};
+use std::{collections::HashMap, fs, path::PathBuf};
 
 use bevy::{
     ecs::{spawn::SpawnWith, system::IntoObserverSystem},
@@ -12,7 +12,10 @@ use bevy::{
 
 use crate::{
     game::configs::{AnimationAssignments, AnimationBlendingConfig},
-    game::configs::assets::SpeedThresholds,
+    game::configs::assets::{
+        AnimationMarker, DirectionalSample, LeanConfig, LocomotionSample, LookAtConfig,
+        ClimbConfig, SpeedThresholds, VaultConfig,
+    },
     screens::Screen,
     theme::{palette::*, widget},
 };
@@ -188,6 +191,20 @@ struct EditorState {
     selected_run_anim: Option<String>,
     /// Selected jump animation
     selected_jump_anim: Option<String>,
+    /// Locomotion blend space samples of the loaded config, kept as-is when saving
+    locomotion: Vec<LocomotionSample>,
+    /// Directional blend space samples of the loaded config, kept as-is when saving
+    directional: Vec<DirectionalSample>,
+    /// Clip event markers of the loaded config, kept as-is when saving
+    markers: HashMap<String, Vec<AnimationMarker>>,
+    /// Procedural lean settings of the loaded config, kept as-is when saving
+    lean: LeanConfig,
+    /// Look-at settings of the loaded config, kept as-is when saving
+    look_at: LookAtConfig,
+    /// Vault warping settings of the loaded config, kept as-is when saving
+    vault: VaultConfig,
+    /// Climb warping settings of the loaded config, kept as-is when saving
+    climb: ClimbConfig,
     /// Playback speed multiplier
     playback_speed: f32,
     /// Is animation playing
@@ -214,6 +231,13 @@ impl Default for EditorState {
             selected_walk_anim: None,
             selected_run_anim: None,
             selected_jump_anim: None,
+            locomotion: Vec::new(),
+            directional: Vec::new(),
+            markers: HashMap::new(),
+            lean: LeanConfig::default(),
+            look_at: LookAtConfig::default(),
+            vault: VaultConfig::default(),
+            climb: ClimbConfig::default(),
             playback_speed: 1.0,
             is_playing: true,
             config_filename: String::from("my_blend_config"),
@@ -828,6 +852,13 @@ fn create_new_config(_: On<Pointer<Click>>, mut editor_state: ResMut<EditorState
             run: editor_state.selected_run_anim.clone(),
             jump: editor_state.selected_jump_anim.clone(),
         },
+        locomotion: editor_state.locomotion.clone(),
+        directional: editor_state.directional.clone(),
+        markers: editor_state.markers.clone(),
+        lean: editor_state.lean.clone(),
+        look_at: editor_state.look_at.clone(),
+        vault: editor_state.vault.clone(),
+        climb: editor_state.climb.clone(),
     };
 
     // Serialize to RON format
@@ -888,6 +919,13 @@ fn save_configuration(_: On<Pointer<Click>>, editor_state: Res<EditorState>) {
             run: editor_state.selected_run_anim.clone(),
             jump: editor_state.selected_jump_anim.clone(),
         },
+        locomotion: editor_state.locomotion.clone(),
+        directional: editor_state.directional.clone(),
+        markers: editor_state.markers.clone(),
+        lean: editor_state.lean.clone(),
+        look_at: editor_state.look_at.clone(),
+        vault: editor_state.vault.clone(),
+        climb: editor_state.climb.clone(),
     };
 
     // Serialize to RON format with pretty printing
@@ -966,6 +1004,13 @@ fn handle_file_selection(
                         editor_state.selected_walk_anim = config.animations.walk.clone();
                         editor_state.selected_run_anim = config.animations.run.clone();
                         editor_state.selected_jump_anim = config.animations.jump.clone();
+                        editor_state.locomotion = config.locomotion.clone();
+                        editor_state.directional = config.directional.clone();
+                        editor_state.markers = config.markers.clone();
+                        editor_state.lean = config.lean.clone();
+                        editor_state.look_at = config.look_at.clone();
+                        editor_state.vault = config.vault.clone();
+                        editor_state.climb = config.climb.clone();
 
                         // Update filename (remove .ron extension and path)
                         if let Some(filename) = event.path.file_stem().and_then(|s| s.to_str()) {